@@ -0,0 +1,10 @@
+-- Sessions issued by the `login` mutation, a token is only valid while its row exists
+CREATE TABLE IF NOT EXISTS `api_session` (
+    `id` VARCHAR(32) NOT NULL,
+    `account_id` INT UNSIGNED NOT NULL,
+    `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `expires_at` DATETIME NOT NULL,
+    PRIMARY KEY (`id`),
+    KEY `idx_account_id` (`account_id`),
+    KEY `idx_expires_at` (`expires_at`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
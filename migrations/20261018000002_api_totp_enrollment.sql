@@ -0,0 +1,7 @@
+-- TOTP secrets waiting to be confirmed with a code before they are moved to `account`.`totp_secret`
+CREATE TABLE IF NOT EXISTS `api_totp_enrollment` (
+    `account_id` INT UNSIGNED NOT NULL,
+    `secret` VARBINARY(128) NOT NULL,
+    `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (`account_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
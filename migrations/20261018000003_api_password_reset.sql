@@ -0,0 +1,10 @@
+-- One-time password reset tokens, only the sha256 of a token is stored
+CREATE TABLE IF NOT EXISTS `api_password_reset` (
+    `token_hash` CHAR(64) NOT NULL,
+    `account_id` INT UNSIGNED NOT NULL,
+    `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `expires_at` DATETIME NOT NULL,
+    `used_at` DATETIME NULL DEFAULT NULL,
+    PRIMARY KEY (`token_hash`),
+    KEY `idx_account_id` (`account_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,9 @@
+-- Accounts registered while email verification is required, they stay banned until the token is confirmed
+CREATE TABLE IF NOT EXISTS `api_email_verification` (
+    `account_id` INT UNSIGNED NOT NULL,
+    `token_hash` CHAR(64) NOT NULL,
+    `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `expires_at` DATETIME NOT NULL,
+    PRIMARY KEY (`account_id`),
+    UNIQUE KEY `idx_token_hash` (`token_hash`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,15 @@
+-- Time-limited account_access rows, revoked_at is set once the row was removed again
+CREATE TABLE IF NOT EXISTS `api_access_grant` (
+    `id` INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `account_id` INT UNSIGNED NOT NULL,
+    `realm_id` INT NOT NULL,
+    `gmlevel` TINYINT UNSIGNED NOT NULL,
+    `granted_by` VARCHAR(32) NOT NULL,
+    `granted_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `expires_at` DATETIME NOT NULL,
+    `revoked_at` DATETIME NULL,
+    `revoke_reason` VARCHAR(16) NULL,
+    PRIMARY KEY (`id`),
+    KEY `idx_account_realm` (`account_id`, `realm_id`),
+    KEY `idx_pending` (`revoked_at`, `expires_at`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -1,2 +1,14 @@
 pub mod account;
-pub mod db;
\ No newline at end of file
+pub mod db;
+pub mod error;
+pub mod guard;
+pub mod iplocation;
+pub mod loader;
+pub mod lockout;
+pub mod scalar;
+pub mod session;
+pub mod srp;
+pub mod token;
+pub mod totp;
+pub mod validation;
+pub mod verification;
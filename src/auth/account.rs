@@ -1,20 +1,54 @@
-use async_graphql::{Context, Object, Result, Schema, Subscription};
+use std::net::IpAddr;
 
+use async_graphql::{
+    connection::{self, Connection, Edge},
+    dataloader::DataLoader,
+    Context, Object, Result, Schema, Subscription,
+};
+
+use chrono::Duration;
 use futures_util::Stream;
-use log::debug;
+use log::{debug, error};
 use sqlx::types::chrono::{DateTime, Utc};
-use struct_field_names_as_array::FieldNamesAsArray;
 
-use self::{access::Access, realmcharacters::RealmCharacter};
+use self::{
+    access::Access,
+    ban::{Ban, IpBan},
+    expansion::Expansion,
+    filter::{check_page_size, AccountCursor, AccountFilter, AccountOrder, OrderDirection},
+    mute::Mute,
+    projection::Projection,
+    realmcharacters::RealmCharacter,
+    recruit::Recruit,
+};
 
-use super::db::Storage;
+use super::db::{AccountPage, NewAccount, Storage};
+use super::error::ApiError;
+use super::guard::{caller, client_ip, Role, RoleGuard, Visibility, VisibilityGuard};
+use super::iplocation::IpLocation;
+use super::loader::{AccessLoader, RealmCharactersLoader};
+use super::lockout::{self, AttemptLimiter};
+use super::scalar::{Bytes, Email, IpAddress, Timestamp, Username};
+use super::session::Session;
+use super::totp::{self, TotpEnrollment};
+use super::validation::{ValidationErrors, Validator};
+use super::verification::{create_verification_mail, Verification};
+use super::{srp, token};
+use crate::config::Config;
+use crate::mail::{Mail, SharedMailer};
 
 pub mod access;
+pub mod ban;
+pub mod expansion;
+pub mod filter;
+pub mod mute;
+pub mod projection;
 pub mod realmcharacters;
+pub mod recruit;
 
 pub type ID = u64;
 
-#[derive(Clone, Debug, Default, sqlx::FromRow, FieldNamesAsArray)]
+#[derive(Clone, Debug, Default, sqlx::FromRow)]
 pub struct Account {
     #[sqlx(default)]
     pub id: ID,
@@ -69,87 +103,172 @@ pub struct Account {
 #[Object]
 impl Account {
     async fn id(&self) -> u64 {
-        self.id.clone()
+        self.id
     }
-    async fn username(&self) -> String {
-        self.username.clone()
+    async fn username(&self) -> Username {
+        Username(self.username.clone())
     }
 
-    async fn email(&self) -> String {
-        self.email.clone()
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Private)")]
+    async fn email(&self) -> Option<Email> {
+        non_empty(&self.email).map(Email)
     }
-    async fn salt(&self) -> Vec<u8> {
-        Vec::from(self.salt.as_slice())
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Private)")]
+    async fn reg_mail(&self) -> Option<Email> {
+        non_empty(&self.reg_mail).map(Email)
     }
-
-    async fn verifier(&self) -> Vec<u8> {
-        Vec::from(self.verifier.as_slice())
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Secret)")]
+    async fn salt(&self) -> Option<Bytes> {
+        Some(Bytes(self.salt.clone()))
     }
-    async fn session_key(&self) -> Option<Vec<u8>> {
-        self.session_key.clone()
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Secret)")]
+    async fn verifier(&self) -> Option<Bytes> {
+        Some(Bytes(self.verifier.clone()))
     }
-    async fn totp_secret(&self) -> Option<Vec<u8>> {
-        self.totp_secret.clone()
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Secret)")]
+    async fn session_key(&self) -> Option<Bytes> {
+        self.session_key.clone().map(Bytes)
     }
-
-    async fn joindate(&self) -> String {
-        self.joindate.to_rfc3339()
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Secret)")]
+    async fn totp_secret(&self) -> Option<Bytes> {
+        self.totp_secret.clone().map(Bytes)
     }
 
-    async fn last_ip(&self) -> String {
-        self.last_ip.clone()
+    async fn joindate(&self) -> Timestamp {
+        Timestamp(self.joindate)
     }
 
-    async fn last_attempt_ip(&self) -> String {
-        self.last_attempt_ip.clone()
+    /// Null for accounts that never logged in
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Private)")]
+    async fn last_ip(&self) -> Option<IpAddress> {
+        IpAddress::from_stored(&self.last_ip)
+    }
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Private)")]
+    async fn last_attempt_ip(&self) -> Option<IpAddress> {
+        IpAddress::from_stored(&self.last_attempt_ip)
     }
 
     async fn failed_logins(&self) -> u32 {
-        self.failed_logins.clone()
+        self.failed_logins
     }
 
     async fn locked(&self) -> u8 {
-        self.locked.clone()
+        self.locked
     }
 
-    async fn lock_country(&self) -> String {
-        self.lock_country.clone()
+    /// Null when the account is not locked to a country, which is stored as `00`
+    async fn lock_country(&self) -> Option<String> {
+        non_empty(&self.lock_country).filter(|c| c != "00")
     }
 
-    async fn last_login(&self) -> String {
-        self.last_login
-            .map_or_else(|| "".to_string(), |d| d.to_rfc3339())
+    async fn last_login(&self) -> Option<Timestamp> {
+        self.last_login.map(Timestamp)
     }
 
     async fn online(&self) -> u8 {
-        self.online.clone()
+        self.online
     }
 
-    async fn expansion(&self) -> u8 {
-        self.expansion.clone()
+    async fn expansion(&self) -> Expansion {
+        Expansion::from(self.expansion)
     }
 
     async fn os(&self) -> String {
         self.os.clone()
     }
-    async fn recruiter(&self) -> u32 {
-        self.recruiter.clone()
+    /// Negative `mutetime` is a mute given while offline, it starts counting at the next login
+    async fn is_muted(&self) -> bool {
+        self.mutetime < 0 || self.mutetime > Utc::now().timestamp()
+    }
+    /// Null when not muted or when the mute only starts at the next login
+    async fn mute_expires_at(&self) -> Option<Timestamp> {
+        (self.mutetime > Utc::now().timestamp()).then(|| Timestamp::from_unix(self.mutetime))
+    }
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Private)")]
+    async fn mutereason(&self) -> Option<String> {
+        non_empty(&self.mutereason)
+    }
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Private)")]
+    async fn muteby(&self) -> Option<String> {
+        non_empty(&self.muteby)
+    }
+    /// The account that recruited this one through Recruit-a-Friend
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Private)")]
+    async fn recruiter(&self, ctx: &Context<'_>) -> Result<Option<Recruit>, ApiError> {
+        if self.recruiter == 0 {
+            return Ok(None);
+        }
+        let db = ctx.data_unchecked::<Storage>();
+        db.get_recruit(self.recruiter as ID).await
+    }
+    /// Accounts recruited by this one
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Private)")]
+    async fn recruits(&self, ctx: &Context<'_>) -> Result<Vec<Recruit>, ApiError> {
+        let db = ctx.data_unchecked::<Storage>();
+        db.get_recruits(self.id).await
     }
     async fn totaltime(&self) -> u32 {
-        self.totaltime.clone()
+        self.totaltime
+    }
+    async fn access(&self, ctx: &Context<'_>) -> Result<Vec<Access>, ApiError> {
+        let loader = ctx.data_unchecked::<DataLoader<AccessLoader>>();
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
+    }
+    async fn realmcharacters(&self, ctx: &Context<'_>) -> Result<Vec<RealmCharacter>, ApiError> {
+        let loader = ctx.data_unchecked::<DataLoader<RealmCharactersLoader>>();
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
     }
-    async fn access(&self, ctx: &Context<'_>) -> Result<Vec<Access>, String> {
-        let db = ctx.data_unchecked::<Storage>().lock().await;
-        let fields = ctx
-            .field()
-            .selection_set()
-            .map(|field| field.name())
-            .collect::<Vec<_>>();
-        db.access_by_user_id(self.id, &fields).await
+    /// Mute history from `account_muted`, newest first
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Private)")]
+    async fn mutes(&self, ctx: &Context<'_>) -> Result<Vec<Mute>, ApiError> {
+        let db = ctx.data_unchecked::<Storage>();
+        db.account_mutes(self.id).await
+    }
+    /// Current and past bans of the account, newest first
+    #[graphql(guard = "VisibilityGuard::new(self.id, Visibility::Private)")]
+    async fn bans(&self, ctx: &Context<'_>) -> Result<Vec<Ban>, ApiError> {
+        let db = ctx.data_unchecked::<Storage>();
+        db.account_bans(Some(self.id), false).await
+    }
+}
+
+/// Text columns of the auth tables are `NOT NULL DEFAULT ''`, empty ones are returned as null
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+/// Columns for the fields selected on the nodes of a connection, through `edges { node }` or `nodes`
+fn connection_node_projection(ctx: &Context<'_>) -> Projection {
+    let mut nodes = Vec::new();
+    for field in ctx.field().selection_set() {
+        match field.name() {
+            "edges" => {
+                for edge_field in field.selection_set().filter(|f| f.name() == "node") {
+                    nodes.extend(edge_field.selection_set());
+                }
+            }
+            "nodes" => nodes.extend(field.selection_set()),
+            _ => {}
+        }
     }
-    async fn realmcharacters(&self, ctx: &Context<'_>) -> Result<Vec<RealmCharacter>, String> {
-        let db = ctx.data_unchecked::<Storage>().lock().await;
-        db.realmcharacters_by_user_id(self.id).await
+    Projection::account(nodes.into_iter())
+}
+
+/// Fields of the `accounts` connection besides edges and page info
+pub struct AccountConnectionFields {
+    filter: AccountFilter,
+}
+
+#[Object]
+impl AccountConnectionFields {
+    /// Number of accounts matching the filter, only counted when selected
+    async fn total_count(&self, ctx: &Context<'_>) -> Result<u64, ApiError> {
+        ctx.data_unchecked::<Storage>()
+            .count_accounts(&self.filter)
+            .await
     }
 }
 
@@ -157,48 +276,133 @@ pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
-    async fn accounts(&self, ctx: &Context<'_>) -> Vec<Account> {
-        let db = ctx.data_unchecked::<Storage>().lock().await;
-        let fields = ctx
-            .field()
-            .selection_set()
-            .map(|field| field.name())
-            .collect::<Vec<_>>();
-        debug!("ctx.field = {:?}", &fields);
-        debug!(
-            "Account::FIELD_NAMES_AS_ARRAY = {:?}",
-            Account::FIELD_NAMES_AS_ARRAY
-        );
-
-        let accounts = db.get_accounts_with_fields(&fields).await;
-        accounts
+    /// The account of the logged in user
+    async fn me(&self, ctx: &Context<'_>) -> Result<Account, ApiError> {
+        Ok(caller(ctx)?.account.clone())
     }
-    async fn account(&self, ctx: &Context<'_>, id: u64) -> Result<Account, String> {
-        let db = ctx.data_unchecked::<Storage>().lock().await;
-        let fields = ctx
-            .field()
-            .selection_set()
-            .map(|field| field.name())
-            .collect::<Vec<_>>();
+    /// Accounts matching `filter` as a Relay connection, pages hold at most 500 accounts
+    #[allow(clippy::too_many_arguments)]
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn accounts(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: AccountFilter,
+        #[graphql(default)] order_by: AccountOrder,
+        #[graphql(default)] direction: OrderDirection,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<AccountCursor, Account, AccountConnectionFields>> {
+        check_page_size(first, last)?;
+        AccountCursor::check("after", after.as_deref())?;
+        AccountCursor::check("before", before.as_deref())?;
+        let db = ctx.data_unchecked::<Storage>();
+        let projection = connection_node_projection(ctx);
+        debug!("projection = {:?}", &projection);
+        connection::query(
+            after,
+            before,
+            first,
+            last,
+            |after: Option<AccountCursor>, before: Option<AccountCursor>, first, last| async move {
+                let backward = first.is_none() && last.is_some();
+                let limit = first
+                    .or(last)
+                    .unwrap_or(DEFAULT_PAGE_SIZE)
+                    .min(MAX_PAGE_SIZE);
+                let page = AccountPage {
+                    filter: &filter,
+                    order: order_by,
+                    direction,
+                    after: after.as_ref(),
+                    before: before.as_ref(),
+                    limit: limit + 1,
+                    backward,
+                };
+                let mut accounts = db.get_accounts_page(&projection, &page).await?;
+                let has_more = accounts.len() > limit;
+                if has_more {
+                    if backward {
+                        accounts.remove(0);
+                    } else {
+                        accounts.pop();
+                    }
+                }
+                let (has_previous_page, has_next_page) = if backward {
+                    (has_more, before.is_some())
+                } else {
+                    (after.is_some(), has_more)
+                };
+                let mut connection = Connection::with_additional_fields(
+                    has_previous_page,
+                    has_next_page,
+                    AccountConnectionFields {
+                        filter: filter.clone(),
+                    },
+                );
+                connection.edges.extend(
+                    accounts
+                        .into_iter()
+                        .map(|account| Edge::new(AccountCursor::new(order_by, &account), account)),
+                );
+                Ok::<_, ApiError>(connection)
+            },
+        )
+        .await
+    }
+    async fn account(&self, ctx: &Context<'_>, id: u64) -> Result<Account, ApiError> {
+        if !caller(ctx)?.can_read_account(id) {
+            return Err(ApiError::forbidden());
+        }
+        let db = ctx.data_unchecked::<Storage>();
+        let projection = Projection::account(ctx.field().selection_set());
 
-        let accounts = db.get_account_by_id(id, &fields).await;
+        let accounts = db.get_account_by_id(id, &projection).await;
         accounts
     }
-    async fn check_username(&self, ctx: &Context<'_>, username: String) -> Result<bool, String> {
-        let db = ctx.data_unchecked::<Storage>().lock().await;
+    /// Limited per ip like logins with unknown usernames, as it allows to enumerate accounts
+    async fn check_username(
+        &self,
+        ctx: &Context<'_>,
+        username: Username,
+    ) -> Result<bool, ApiError> {
+        if !ctx.data_unchecked::<AttemptLimiter>().hit(client_ip(ctx)?) {
+            return Err(ApiError::rate_limited());
+        }
+        let db = ctx.data_unchecked::<Storage>();
 
-        db.has_account_username(&username).await
+        db.has_account_username(username.as_str()).await
     }
-
-    async fn account_set_password(
+    /// Accounts with an access level on any realm
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn staff(&self, ctx: &Context<'_>) -> Result<Vec<Account>, ApiError> {
+        let db = ctx.data_unchecked::<Storage>();
+        let projection = Projection::account(ctx.field().selection_set());
+        db.get_staff_with_fields(&projection).await
+    }
+    /// Bans of an account or of all accounts when `accountId` is omitted
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn account_bans(
         &self,
         ctx: &Context<'_>,
-        username: String,
-        password: String,
-    ) -> Result<bool, String> {
-        let db = ctx.data_unchecked::<Storage>().lock().await;
-
-        db.set_username_password(username, password).await
+        account_id: Option<u64>,
+        #[graphql(default)] active_only: bool,
+    ) -> Result<Vec<Ban>, ApiError> {
+        let db = ctx.data_unchecked::<Storage>();
+        db.account_bans(account_id, active_only).await
+    }
+    /// Bans of an ip or of all ips when `ip` is omitted
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn ip_bans(
+        &self,
+        ctx: &Context<'_>,
+        ip: Option<IpAddress>,
+        #[graphql(default)] active_only: bool,
+    ) -> Result<Vec<IpBan>, ApiError> {
+        let db = ctx.data_unchecked::<Storage>();
+        let ip = ip.map(|ip| ip.0.to_string());
+        db.ip_bans(ip.as_deref(), active_only).await
     }
 }
 
@@ -206,20 +410,648 @@ pub struct MutationRoot;
 
 #[Object]
 impl MutationRoot {
+    /// Registration is open to anonymous callers.
+    /// With `REQUIRE_EMAIL_VERIFICATION` the account stays locked until `verifyEmail` is called.
+    /// `recruiter` is the username of the account that recruited this one through Recruit-a-Friend.
+    /// Staff can pass `gmlevel` to create accounts with access on all realms, up to their own level.
+    /// Arguments are plain strings so the `Validator` reports every problem at once in `extensions.fields`.
     async fn create_account(
         &self,
         ctx: &Context<'_>,
         username: String,
         password: String,
         email: String,
-    ) -> Result<u64, String> {
-        let auth_db = ctx.data_unchecked::<Storage>().lock().await;
-        auth_db.create_account(&username, &password, &email).await
+        recruiter: Option<String>,
+        gmlevel: Option<u8>,
+    ) -> Result<u64, ApiError> {
+        let config = ctx.data_unchecked::<Config>();
+        let (username, email) = (username.as_str(), email.as_str());
+        let gmlevel = gmlevel.filter(|gmlevel| *gmlevel > 0);
+        if let Some(gmlevel) = gmlevel {
+            let caller = caller(ctx)?;
+            if gmlevel > Role::Administrator as u8
+                || !caller.is_at_least(Role::GameMaster)
+                || Role::from(gmlevel) > caller.role()
+            {
+                return Err(ApiError::forbidden());
+            }
+        }
+        ctx.data_unchecked::<Validator>()
+            .new_account(username, &password, email)?;
+        if recruiter
+            .as_deref()
+            .is_some_and(|r| r.eq_ignore_ascii_case(username))
+        {
+            return Err(ValidationErrors::single(
+                "recruiter",
+                "SELF_RECRUIT",
+                "An account cannot recruit itself",
+            )
+            .into());
+        }
+        let auth_db = ctx.data_unchecked::<Storage>();
+        let recruiter_id = match recruiter {
+            Some(recruiter) => Some(
+                auth_db
+                    .get_account_id_by_username(&recruiter)
+                    .await?
+                    .ok_or_else(|| {
+                        ValidationErrors::single(
+                            "recruiter",
+                            "NOT_FOUND",
+                            "Recruiter does not exist",
+                        )
+                    })?,
+            ),
+            None => None,
+        };
+        let verification = config
+            .require_email_verification()
+            .then(|| Verification::new(config));
+        let account_id = auth_db
+            .create_account(&NewAccount {
+                username,
+                password: &password,
+                email,
+                expansion: config.default_expansion(),
+                recruiter: recruiter_id,
+                gmlevel,
+                verification: verification.as_ref(),
+            })
+            .await?;
+        if let Some(verification) = verification {
+            let mail = verification.mail(config, username, email);
+            if let Err(e) = ctx.data_unchecked::<SharedMailer>().send(&mail).await {
+                error!("Verification mail cannot be sent: {}", e);
+            }
+        }
+        Ok(account_id)
+    }
+
+    async fn verify_email(&self, ctx: &Context<'_>, token: String) -> Result<bool, ApiError> {
+        let auth_db = ctx.data_unchecked::<Storage>();
+        auth_db
+            .verify_email(&token::hash(&token))
+            .await?
+            .map(|_| true)
+            .ok_or_else(|| ApiError::NotFound("Invalid or expired verification token".to_string()))
+    }
+
+    /// Mails a new verification token to the `reg_mail` of an account that is not verified yet.
+    /// Always returns true, so it cannot be used to find out which accounts exist.
+    /// Counts as an attempt of the client ip like `requestPasswordReset`.
+    async fn resend_verification_email(
+        &self,
+        ctx: &Context<'_>,
+        username: Username,
+    ) -> Result<bool, ApiError> {
+        if !ctx.data_unchecked::<AttemptLimiter>().hit(client_ip(ctx)?) {
+            return Err(ApiError::rate_limited());
+        }
+        let config = ctx.data_unchecked::<Config>();
+        let auth_db = ctx.data_unchecked::<Storage>();
+        let username = username.as_str();
+        if let Some((account_id, reg_mail)) = auth_db.pending_email_verification(username).await? {
+            let mail =
+                create_verification_mail(auth_db, config, account_id, username, &reg_mail).await?;
+            if let Err(e) = ctx.data_unchecked::<SharedMailer>().send(&mail).await {
+                error!("Verification mail cannot be sent: {}", e);
+            }
+        }
+        Ok(true)
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Administrator)")]
+    async fn delete_account(&self, ctx: &Context<'_>, id: u64) -> Result<bool, ApiError> {
+        let caller = caller(ctx)?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        if !caller.outranks(&auth_db.access_by_user_id(id).await?) {
+            return Err(ApiError::forbidden());
+        }
+        auth_db.delete_account(id).await?;
+        Ok(true)
+    }
+
+    /// Bans an account for `duration` seconds, 0 bans it permanently. Its sessions are revoked.
+    /// A shorter ban does not replace a longer one that is still active.
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn ban_account(
+        &self,
+        ctx: &Context<'_>,
+        id: u64,
+        duration: u32,
+        reason: String,
+    ) -> Result<bool, ApiError> {
+        let caller = caller(ctx)?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        if !auth_db.has_account_id(id).await? {
+            return Err(ApiError::NotFound("Account not found".to_string()));
+        }
+        if !caller.outranks(&auth_db.access_by_user_id(id).await?) {
+            return Err(ApiError::forbidden());
+        }
+        let ends_at = Utc::now().timestamp() + duration as i64;
+        let longer_ban =
+            auth_db.account_bans(Some(id), true).await?.iter().any(|b| {
+                b.bandate == b.unbandate || (duration != 0 && b.unbandate as i64 >= ends_at)
+            });
+        if longer_ban {
+            return Err(ApiError::AlreadyExists(
+                "A longer ban is already active".to_string(),
+            ));
+        }
+        auth_db
+            .ban_account(id, duration, &caller.account.username, &reason)
+            .await?;
+        auth_db.delete_account_sessions(id, None).await?;
+        Ok(true)
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn unban_account(&self, ctx: &Context<'_>, id: u64) -> Result<bool, ApiError> {
+        let caller = caller(ctx)?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        if !caller.outranks(&auth_db.access_by_user_id(id).await?) {
+            return Err(ApiError::forbidden());
+        }
+        auth_db.unban_account(id).await
+    }
+
+    /// Sets the gmlevel of an account on a realm, -1 for all realms, like `.account set gmlevel` does.
+    /// Callers cannot grant a level above their own or change accounts that do not rank below them.
+    /// With `duration` the access is removed again after that many seconds.
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn set_gm_level(
+        &self,
+        ctx: &Context<'_>,
+        account_id: u64,
+        gmlevel: u8,
+        #[graphql(default = -1)] realm_id: i32,
+        comment: Option<String>,
+        duration: Option<u32>,
+    ) -> Result<bool, ApiError> {
+        let caller = caller(ctx)?;
+        if duration == Some(0) {
+            return Err(ApiError::invalid(
+                "duration",
+                "TOO_SHORT",
+                "A grant needs a duration of at least one second",
+            ));
+        }
+        if gmlevel > Role::Administrator as u8 {
+            return Err(ApiError::invalid(
+                "gmlevel",
+                "OUT_OF_RANGE",
+                "Invalid gmlevel",
+            ));
+        }
+        if Role::from(gmlevel) > caller.role() {
+            return Err(ApiError::Forbidden(
+                "Cannot grant a gmlevel above your own".to_string(),
+            ));
+        }
+        let auth_db = ctx.data_unchecked::<Storage>();
+        if !auth_db.has_account_id(account_id).await? {
+            return Err(ApiError::NotFound("Account not found".to_string()));
+        }
+        if realm_id != -1 && !auth_db.has_realm(realm_id).await? {
+            return Err(ApiError::NotFound("Realm not found".to_string()));
+        }
+        if !caller.outranks(&auth_db.access_by_user_id(account_id).await?) {
+            return Err(ApiError::forbidden());
+        }
+        auth_db
+            .set_access(
+                account_id,
+                gmlevel,
+                realm_id,
+                comment.as_deref(),
+                duration.map(|d| {
+                    (
+                        Utc::now() + Duration::seconds(d as i64),
+                        caller.account.username.as_str(),
+                    )
+                }),
+            )
+            .await?;
+        Ok(true)
+    }
+
+    /// Removes the access row of an account for a realm, -1 removes the one for all realms
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn revoke_gm_level(
+        &self,
+        ctx: &Context<'_>,
+        account_id: u64,
+        #[graphql(default = -1)] realm_id: i32,
+    ) -> Result<bool, ApiError> {
+        let caller = caller(ctx)?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        if !caller.outranks(&auth_db.access_by_user_id(account_id).await?) {
+            return Err(ApiError::forbidden());
+        }
+        auth_db.delete_access(account_id, realm_id).await
+    }
+
+    /// Locks the logged in account to the ip of this request, logins from other ips are refused
+    async fn lock_to_ip(&self, ctx: &Context<'_>) -> Result<bool, ApiError> {
+        let account_id = caller(ctx)?.account.id;
+        // `account.last_ip` only fits ipv4 addresses
+        let ip = match client_ip(ctx)? {
+            IpAddr::V4(ip) => ip,
+            IpAddr::V6(_) => {
+                return Err(ApiError::Forbidden(
+                    "Only ipv4 addresses can be locked to".to_string(),
+                ))
+            }
+        };
+        let auth_db = ctx.data_unchecked::<Storage>();
+        auth_db
+            .lock_account_to_ip(account_id, &ip.to_string())
+            .await?;
+        Ok(true)
+    }
+
+    /// Locks the logged in account to the country of this request's ip like `.account lock country`,
+    /// returns the lowercase country code
+    async fn lock_to_country(&self, ctx: &Context<'_>) -> Result<String, ApiError> {
+        let account_id = caller(ctx)?.account.id;
+        let country = ctx
+            .data_unchecked::<IpLocation>()
+            .country_code(client_ip(ctx)?)
+            .ok_or_else(|| ApiError::NotFound("Country of your ip is unknown".to_string()))?
+            .to_string();
+        let auth_db = ctx.data_unchecked::<Storage>();
+        auth_db
+            .lock_account_to_country(account_id, &country)
+            .await?;
+        Ok(country)
+    }
+
+    /// Removes the ip and country lock of an account
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn clear_account_lock(&self, ctx: &Context<'_>, id: u64) -> Result<bool, ApiError> {
+        let caller = caller(ctx)?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        if !caller.outranks(&auth_db.access_by_user_id(id).await?) {
+            return Err(ApiError::forbidden());
+        }
+        auth_db.clear_account_lock(id).await
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn set_expansion(
+        &self,
+        ctx: &Context<'_>,
+        account_id: u64,
+        expansion: Expansion,
+    ) -> Result<bool, ApiError> {
+        let caller = caller(ctx)?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        if !caller.outranks(&auth_db.access_by_user_id(account_id).await?) {
+            return Err(ApiError::forbidden());
+        }
+        auth_db.set_expansion(account_id, expansion).await
+    }
+
+    /// Mutes an account for `minutes` like `.mute` does: online players are muted right away,
+    /// for offline ones the mute is stored negated and starts at their next login.
+    #[graphql(guard = "RoleGuard::new(Role::Moderator)")]
+    async fn mute_account(
+        &self,
+        ctx: &Context<'_>,
+        id: u64,
+        minutes: u32,
+        reason: String,
+    ) -> Result<bool, ApiError> {
+        if minutes == 0 {
+            return Err(ApiError::invalid(
+                "minutes",
+                "TOO_SHORT",
+                "A mute needs at least one minute",
+            ));
+        }
+        let caller = caller(ctx)?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        let target = auth_db
+            .get_account_by_id(id, &Projection::of(&["online"]))
+            .await?;
+        if !caller.outranks(&auth_db.access_by_user_id(id).await?) {
+            return Err(ApiError::forbidden());
+        }
+        let seconds = minutes as i64 * 60;
+        let mutetime = if target.online != 0 {
+            Utc::now().timestamp() + seconds
+        } else {
+            -seconds
+        };
+        auth_db
+            .mute_account(id, mutetime, minutes, &caller.account.username, &reason)
+            .await?;
+        Ok(true)
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Moderator)")]
+    async fn unmute_account(&self, ctx: &Context<'_>, id: u64) -> Result<bool, ApiError> {
+        let caller = caller(ctx)?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        if !caller.outranks(&auth_db.access_by_user_id(id).await?) {
+            return Err(ApiError::forbidden());
+        }
+        auth_db.unmute_account(id).await
+    }
+
+    /// Bans an ipv4 address for `duration` seconds, 0 bans it permanently
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn ban_ip(
+        &self,
+        ctx: &Context<'_>,
+        ip: IpAddress,
+        duration: u32,
+        reason: String,
+    ) -> Result<bool, ApiError> {
+        let caller = caller(ctx)?;
+        // `ip_banned.ip` only fits ipv4 addresses
+        let ip = match ip.0 {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(_) => {
+                return Err(ApiError::invalid(
+                    "ip",
+                    "INVALID_FORMAT",
+                    "Only ipv4 addresses can be banned",
+                ))
+            }
+        };
+        let auth_db = ctx.data_unchecked::<Storage>();
+        auth_db
+            .ban_ip(&ip, duration, &caller.account.username, &reason)
+            .await?;
+        Ok(true)
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::GameMaster)")]
+    async fn unban_ip(&self, ctx: &Context<'_>, ip: IpAddress) -> Result<bool, ApiError> {
+        let auth_db = ctx.data_unchecked::<Storage>();
+        auth_db.unban_ip(&ip.0.to_string()).await
+    }
+
+    /// `totp` is required for accounts with two-factor authentication enabled
+    async fn login(
+        &self,
+        ctx: &Context<'_>,
+        username: String,
+        password: String,
+        totp: Option<String>,
+    ) -> Result<Session, ApiError> {
+        let config = ctx.data_unchecked::<Config>();
+        let limiter = ctx.data_unchecked::<AttemptLimiter>();
+        let ip = client_ip(ctx)?;
+        if limiter.is_limited(ip) {
+            return Err(ApiError::rate_limited());
+        }
+        let auth_db = ctx.data_unchecked::<Storage>();
+        if auth_db.is_ip_banned(&ip.to_string()).await? {
+            return Err(ApiError::Forbidden("Your ip is banned".to_string()));
+        }
+        let account_id = match auth_db.check_credentials(&username, &password).await? {
+            Some((account_id, true)) => account_id,
+            Some((account_id, false)) => {
+                lockout::record_failed_login(auth_db, config, account_id, ip).await?;
+                return Err(limiter.fail(
+                    ip,
+                    ApiError::Unauthenticated("Invalid username or password".to_string()),
+                ));
+            }
+            None => {
+                return Err(limiter.fail(
+                    ip,
+                    ApiError::Unauthenticated("Invalid username or password".to_string()),
+                ));
+            }
+        };
+        if auth_db.has_pending_email_verification(account_id).await? {
+            return Err(ApiError::Forbidden("Email is not verified".to_string()));
+        }
+        if auth_db.is_account_banned(account_id).await? {
+            return Err(ApiError::Forbidden("Account is banned".to_string()));
+        }
+        let ip_location = ctx.data_unchecked::<IpLocation>();
+        lockout::check_account_lock(auth_db, ip_location, account_id, ip).await?;
+        if let Some(secret) = auth_db.get_totp_secret(account_id).await? {
+            let secret = totp::decrypt_secret(config.totp_master_key(), &secret)?;
+            match totp {
+                Some(code) if totp::validate_token(&secret, &code) => {}
+                Some(_) => {
+                    lockout::record_failed_login(auth_db, config, account_id, ip).await?;
+                    return Err(limiter.fail(
+                        ip,
+                        ApiError::Unauthenticated("Invalid TOTP code".to_string()),
+                    ));
+                }
+                None => return Err(ApiError::Unauthenticated("TOTP code required".to_string())),
+            }
+        }
+        auth_db
+            .reset_failed_logins(account_id, &ip.to_string())
+            .await?;
+        let session = Session::issue(config, account_id)?;
+        auth_db.create_session(session.claims()).await?;
+        Ok(session)
+    }
+
+    async fn logout(&self, ctx: &Context<'_>) -> Result<bool, ApiError> {
+        let claims = &caller(ctx)?.claims;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        auth_db.delete_session(&claims.jti).await
+    }
+
+    /// Issues a new token for a still valid session and revokes the old one
+    async fn refresh_token(&self, ctx: &Context<'_>) -> Result<Session, ApiError> {
+        let config = ctx.data_unchecked::<Config>();
+        let claims = &caller(ctx)?.claims;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        let session = Session::issue(config, claims.sub)?;
+        auth_db.create_session(session.claims()).await?;
+        auth_db.delete_session(&claims.jti).await?;
+        Ok(session)
+    }
+
+    /// Changes the password of the logged in account, other sessions of it are revoked
+    async fn change_password(
+        &self,
+        ctx: &Context<'_>,
+        old_password: String,
+        new_password: String,
+    ) -> Result<bool, ApiError> {
+        let caller = caller(ctx)?;
+        let account = &caller.account;
+        ctx.data_unchecked::<Validator>().password(
+            "newPassword",
+            &new_password,
+            Some(&account.username),
+        )?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        if !srp::verify_password(
+            &account.username,
+            &old_password,
+            &account.salt,
+            &account.verifier,
+        )? {
+            let config = ctx.data_unchecked::<Config>();
+            let ip = client_ip(ctx)?;
+            lockout::record_failed_login(auth_db, config, account.id, ip).await?;
+            return Err(ctx.data_unchecked::<AttemptLimiter>().fail(
+                ip,
+                ApiError::invalid("oldPassword", "INCORRECT", "Invalid password"),
+            ));
+        }
+        let updated = auth_db
+            .set_account_password(account.id, &new_password)
+            .await?;
+        auth_db
+            .delete_account_sessions(account.id, Some(&caller.claims.jti))
+            .await?;
+        Ok(updated)
+    }
+
+    /// Sets the password of an account of a lower role and revokes its sessions
+    #[graphql(guard = "RoleGuard::new(Role::Administrator)")]
+    async fn admin_set_password(
+        &self,
+        ctx: &Context<'_>,
+        account_id: u64,
+        password: String,
+    ) -> Result<bool, ApiError> {
+        let caller = caller(ctx)?;
+        ctx.data_unchecked::<Validator>()
+            .password("password", &password, None)?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        if !caller.outranks(&auth_db.access_by_user_id(account_id).await?) {
+            return Err(ApiError::forbidden());
+        }
+        let updated = auth_db.set_account_password(account_id, &password).await?;
+        auth_db.delete_account_sessions(account_id, None).await?;
+        Ok(updated)
+    }
+
+    /// Mails a password reset token to every account registered with the email.
+    /// Always returns true, so it cannot be used to find out which emails are registered.
+    /// Counts as an attempt of the client ip like `checkUsername` to keep mailboxes from being flooded.
+    async fn request_password_reset(
+        &self,
+        ctx: &Context<'_>,
+        email: Email,
+    ) -> Result<bool, ApiError> {
+        if !ctx.data_unchecked::<AttemptLimiter>().hit(client_ip(ctx)?) {
+            return Err(ApiError::rate_limited());
+        }
+        let config = ctx.data_unchecked::<Config>();
+        let email = email.as_str();
+        let expires_at = Utc::now() + Duration::seconds(config.password_reset_ttl());
+        let auth_db = ctx.data_unchecked::<Storage>();
+        let mailer = ctx.data_unchecked::<SharedMailer>();
+        for (account_id, username) in auth_db.accounts_by_email(email).await? {
+            let token = token::generate();
+            auth_db
+                .create_password_reset(&token::hash(&token), account_id, expires_at)
+                .await?;
+            let link = config
+                .password_reset_url()
+                .map_or_else(|| token.clone(), |url| url.replace("{token}", &token));
+            let mail = Mail {
+                to: email.to_string(),
+                subject: "Password reset".to_string(),
+                body: format!(
+                    "A password reset was requested for the account {}.\n\n\
+                    Use this to set a new password: {}\n\n\
+                    It expires at {}, ignore this mail if you did not request it.",
+                    username,
+                    link,
+                    expires_at.to_rfc3339()
+                ),
+            };
+            if let Err(e) = mailer.send(&mail).await {
+                error!("Password reset mail cannot be sent: {}", e);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Sets a new password with a token from `requestPasswordReset`, all sessions of the account are revoked
+    async fn reset_password(
+        &self,
+        ctx: &Context<'_>,
+        token: String,
+        new_password: String,
+    ) -> Result<bool, ApiError> {
+        ctx.data_unchecked::<Validator>()
+            .password("newPassword", &new_password, None)?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        let account_id = auth_db
+            .use_password_reset(&token::hash(&token))
+            .await?
+            .ok_or_else(|| {
+                ApiError::NotFound("Invalid or expired password reset token".to_string())
+            })?;
+        let updated = auth_db
+            .set_account_password(account_id, &new_password)
+            .await?;
+        auth_db.delete_account_sessions(account_id, None).await?;
+        Ok(updated)
+    }
+
+    /// Starts two-factor authentication setup, it is enabled once confirmed with `confirmTotp`
+    async fn enroll_totp(&self, ctx: &Context<'_>) -> Result<TotpEnrollment, ApiError> {
+        let config = ctx.data_unchecked::<Config>();
+        let account = &caller(ctx)?.account;
+        if account.totp_secret.is_some() {
+            return Err(ApiError::AlreadyExists(
+                "Two-factor authentication is already enabled".to_string(),
+            ));
+        }
+        let secret = totp::generate_secret();
+        let encrypted = totp::encrypt_secret(config.totp_master_key(), &secret)?;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        auth_db.set_totp_enrollment(account.id, &encrypted).await?;
+        Ok(TotpEnrollment::new(
+            &config.totp_issuer(),
+            &account.username,
+            secret,
+        ))
+    }
+
+    async fn confirm_totp(&self, ctx: &Context<'_>, code: String) -> Result<bool, ApiError> {
+        let config = ctx.data_unchecked::<Config>();
+        let account = &caller(ctx)?.account;
+        let auth_db = ctx.data_unchecked::<Storage>();
+        let encrypted = auth_db
+            .get_totp_enrollment(account.id)
+            .await?
+            .ok_or_else(|| {
+                ApiError::NotFound("No pending two-factor authentication setup".to_string())
+            })?;
+        let secret = totp::decrypt_secret(config.totp_master_key(), &encrypted)?;
+        if !totp::validate_token(&secret, &code) {
+            return Err(ApiError::invalid("code", "INCORRECT", "Invalid TOTP code"));
+        }
+        auth_db
+            .set_totp_secret(account.id, Some(&encrypted))
+            .await?;
+        auth_db.delete_totp_enrollment(account.id).await
     }
 
-    async fn delete_account(&self, ctx: &Context<'_>, id: u64) -> Result<bool> {
-        let auth_db = ctx.data_unchecked::<Storage>().lock().await;
-        Ok(auth_db.delete_account(id).await)
+    async fn disable_totp(&self, ctx: &Context<'_>, code: String) -> Result<bool, ApiError> {
+        let config = ctx.data_unchecked::<Config>();
+        let account = &caller(ctx)?.account;
+        let encrypted = account.totp_secret.as_ref().ok_or_else(|| {
+            ApiError::NotFound("Two-factor authentication is not enabled".to_string())
+        })?;
+        let secret = totp::decrypt_secret(config.totp_master_key(), encrypted)?;
+        if !totp::validate_token(&secret, &code) {
+            return Err(ApiError::invalid("code", "INCORRECT", "Invalid TOTP code"));
+        }
+        let auth_db = ctx.data_unchecked::<Storage>();
+        auth_db.set_totp_secret(account.id, None).await
     }
 }
 
@@ -228,10 +1060,7 @@ pub struct SubscriptionRoot;
 #[Subscription]
 impl SubscriptionRoot {
     async fn values(&self, ctx: &Context<'_>) -> Result<impl Stream<Item = i32>> {
-        println!("{:?}", ctx.data::<Account>());
-        // if ctx.data::<Account>()?.0 != "123456" {
-        //     return Err("Forbidden".into());
-        // }
+        debug!("Subscription of account {}", caller(ctx)?.account.id);
         Ok(futures_util::stream::once(async move { 10 }))
     }
 }
@@ -1,30 +1,39 @@
 use async_graphql::Object;
-use struct_field_names_as_array::FieldNamesAsArray;
+use sqlx::types::chrono::{DateTime, Utc};
 
-#[derive(Clone, Debug, Default, sqlx::FromRow, FieldNamesAsArray)]
+use crate::auth::scalar::Timestamp;
+
+#[derive(Clone, Debug, Default, sqlx::FromRow)]
 pub struct Access {
     #[sqlx(default)]
-    id: u64,
+    pub id: u64,
+    #[sqlx(default)]
+    pub gmlevel: u8,
     #[sqlx(default)]
-    gmlevel: u8,
+    pub realmid: i32,
     #[sqlx(default)]
-    realmid: i32,
+    pub comment: Option<String>,
+    /// End of the pending `api_access_grant` of the row
     #[sqlx(default)]
-    comment: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 #[Object]
 impl Access {
     async fn id(&self) -> u64 {
-        self.id.clone()
+        self.id
     }
     async fn gmlevel(&self) -> u8 {
-        self.gmlevel.clone()
+        self.gmlevel
     }
     async fn realmid(&self) -> i32 {
-        self.realmid.clone()
+        self.realmid
     }
     async fn comment(&self) -> Option<String> {
         self.comment.clone()
     }
+    /// When a time-limited grant ends, null for permanent access
+    async fn expires_at(&self) -> Option<Timestamp> {
+        self.expires_at.map(Timestamp)
+    }
 }
@@ -0,0 +1,84 @@
+use async_graphql::Object;
+use sqlx::types::chrono::Utc;
+
+use crate::auth::scalar::{IpAddress, Timestamp};
+
+/// A row of `account_banned`, bandate and unbandate are unix timestamps
+#[derive(Clone, Debug, Default, sqlx::FromRow)]
+pub struct Ban {
+    pub id: u64,
+    pub bandate: u32,
+    pub unbandate: u32,
+    pub bannedby: String,
+    pub banreason: String,
+    pub active: u8,
+}
+
+/// A row of `ip_banned`
+#[derive(Clone, Debug, Default, sqlx::FromRow)]
+pub struct IpBan {
+    pub ip: String,
+    pub bandate: u32,
+    pub unbandate: u32,
+    pub bannedby: String,
+    pub banreason: String,
+}
+
+/// Bans where `bandate = unbandate` never end, as authserver treats them
+fn is_in_effect(bandate: u32, unbandate: u32) -> bool {
+    bandate == unbandate || unbandate as i64 > Utc::now().timestamp()
+}
+
+#[Object]
+impl Ban {
+    async fn account_id(&self) -> u64 {
+        self.id
+    }
+    async fn bandate(&self) -> Timestamp {
+        Timestamp::from_unix(self.bandate as i64)
+    }
+    /// Null for permanent bans
+    async fn unbandate(&self) -> Option<Timestamp> {
+        (self.bandate != self.unbandate).then(|| Timestamp::from_unix(self.unbandate as i64))
+    }
+    async fn bannedby(&self) -> String {
+        self.bannedby.clone()
+    }
+    async fn banreason(&self) -> String {
+        self.banreason.clone()
+    }
+    async fn permanent(&self) -> bool {
+        self.bandate == self.unbandate
+    }
+    /// Whether the ban still keeps the account from logging in
+    async fn active(&self) -> bool {
+        self.active == 1 && is_in_effect(self.bandate, self.unbandate)
+    }
+}
+
+#[Object]
+impl IpBan {
+    /// Null when the stored value is not an address, `.ban ip` does not check it
+    async fn ip(&self) -> Option<IpAddress> {
+        IpAddress::from_stored(&self.ip)
+    }
+    async fn bandate(&self) -> Timestamp {
+        Timestamp::from_unix(self.bandate as i64)
+    }
+    /// Null for permanent bans
+    async fn unbandate(&self) -> Option<Timestamp> {
+        (self.bandate != self.unbandate).then(|| Timestamp::from_unix(self.unbandate as i64))
+    }
+    async fn bannedby(&self) -> String {
+        self.bannedby.clone()
+    }
+    async fn banreason(&self) -> String {
+        self.banreason.clone()
+    }
+    async fn permanent(&self) -> bool {
+        self.bandate == self.unbandate
+    }
+    async fn active(&self) -> bool {
+        is_in_effect(self.bandate, self.unbandate)
+    }
+}
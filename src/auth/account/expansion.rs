@@ -0,0 +1,19 @@
+use async_graphql::Enum;
+
+/// `account.expansion`, the latest expansion an account may play
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expansion {
+    Classic = 0,
+    Tbc = 1,
+    Wotlk = 2,
+}
+
+impl From<u8> for Expansion {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Expansion::Classic,
+            1 => Expansion::Tbc,
+            _ => Expansion::Wotlk,
+        }
+    }
+}
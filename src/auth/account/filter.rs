@@ -0,0 +1,195 @@
+use async_graphql::{connection::CursorType, Enum, InputObject};
+
+use super::{expansion::Expansion, Account, ID};
+use crate::auth::error::ApiError;
+use crate::auth::scalar::{Email, IpAddress, Timestamp};
+
+/// Conditions of the `accounts` query, all given ones have to match
+#[derive(InputObject, Clone, Debug, Default)]
+pub struct AccountFilter {
+    pub username_prefix: Option<String>,
+    pub email: Option<Email>,
+    pub last_ip: Option<IpAddress>,
+    pub online: Option<bool>,
+    pub locked: Option<bool>,
+    pub expansion: Option<Expansion>,
+    pub joined_after: Option<Timestamp>,
+    pub joined_before: Option<Timestamp>,
+    pub last_login_after: Option<Timestamp>,
+    pub last_login_before: Option<Timestamp>,
+}
+
+#[derive(Enum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AccountOrder {
+    #[default]
+    Id,
+    Username,
+    Email,
+    LastIp,
+    Online,
+    Locked,
+    Expansion,
+    Joindate,
+    LastLogin,
+}
+
+#[derive(Enum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OrderDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Accounts without a login sort before all others
+const NEVER: &str = "1970-01-01 00:00:00";
+const SQL_DATETIME: &str = "%Y-%m-%d %H:%M:%S";
+
+impl AccountOrder {
+    /// Field of `Account` the order needs to be loaded
+    pub fn field(&self) -> &'static str {
+        match self {
+            AccountOrder::Id => "id",
+            AccountOrder::Username => "username",
+            AccountOrder::Email => "email",
+            AccountOrder::LastIp => "last_ip",
+            AccountOrder::Online => "online",
+            AccountOrder::Locked => "locked",
+            AccountOrder::Expansion => "expansion",
+            AccountOrder::Joindate => "joindate",
+            AccountOrder::LastLogin => "last_login",
+        }
+    }
+
+    /// SQL expression accounts are sorted by, `last_login` is nullable
+    pub fn sql(&self) -> &'static str {
+        match self {
+            AccountOrder::LastLogin => "IFNULL(last_login, '1970-01-01 00:00:00')",
+            _ => self.field(),
+        }
+    }
+
+    /// The sort value of an account as it is compared in SQL
+    pub fn value(&self, account: &Account) -> String {
+        match self {
+            AccountOrder::Id => account.id.to_string(),
+            AccountOrder::Username => account.username.clone(),
+            AccountOrder::Email => account.email.clone(),
+            AccountOrder::LastIp => account.last_ip.clone(),
+            AccountOrder::Online => account.online.to_string(),
+            AccountOrder::Locked => account.locked.to_string(),
+            AccountOrder::Expansion => account.expansion.to_string(),
+            AccountOrder::Joindate => account.joindate.format(SQL_DATETIME).to_string(),
+            AccountOrder::LastLogin => account
+                .last_login
+                .map_or_else(|| NEVER.to_string(), |d| d.format(SQL_DATETIME).to_string()),
+        }
+    }
+}
+
+/// Position of an account in an ordered list: the sort value and the id breaking ties
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountCursor {
+    pub value: String,
+    pub id: ID,
+}
+
+impl AccountCursor {
+    pub fn new(order: AccountOrder, account: &Account) -> Self {
+        Self {
+            value: order.value(account),
+            id: account.id,
+        }
+    }
+
+    /// Checks the `after` or `before` argument up front, `connection::query` reports
+    /// cursors it cannot decode as plain messages without an error code
+    pub fn check(field: &'static str, cursor: Option<&str>) -> Result<(), ApiError> {
+        match cursor.map(Self::decode_cursor) {
+            Some(Err(message)) => Err(ApiError::invalid(field, "INVALID_FORMAT", message)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks `first` and `last` up front like `AccountCursor::check`, `connection::query` rejects them
+/// with the same rules but without an error code
+pub fn check_page_size(first: Option<i32>, last: Option<i32>) -> Result<(), ApiError> {
+    if first.is_some() && last.is_some() {
+        return Err(ApiError::invalid(
+            "last",
+            "CONFLICT",
+            "first and last cannot be given together",
+        ));
+    }
+    for (field, size) in [("first", first), ("last", last)] {
+        if size.is_some_and(|size| size < 0) {
+            return Err(ApiError::invalid(
+                field,
+                "OUT_OF_RANGE",
+                format!("{} cannot be negative", field),
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl CursorType for AccountCursor {
+    type Error = String;
+
+    fn decode_cursor(s: &str) -> Result<Self, Self::Error> {
+        let invalid = || "Invalid cursor".to_string();
+        let data = hex::decode(s).map_err(|_| invalid())?;
+        let (value, id): (String, ID) = serde_json::from_slice(&data).map_err(|_| invalid())?;
+        Ok(Self { value, id })
+    }
+
+    fn encode_cursor(&self) -> String {
+        hex::encode(serde_json::to_vec(&(&self.value, self.id)).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors_survive_encoding() {
+        for value in ["", "2024-01-31 12:00:00", "\"quoted\", with commas"] {
+            let cursor = AccountCursor {
+                value: value.to_string(),
+                id: 42,
+            };
+            let encoded = cursor.encode_cursor();
+            assert!(encoded.chars().all(|c| c.is_ascii_hexdigit()));
+            assert_eq!(AccountCursor::decode_cursor(&encoded), Ok(cursor));
+        }
+    }
+
+    #[test]
+    fn malformed_cursors_are_invalid_arguments() {
+        let not_a_tuple = hex::encode(b"{\"value\":\"a\"}");
+        for cursor in ["", "zz", "abc", not_a_tuple.as_str()] {
+            assert!(AccountCursor::decode_cursor(cursor).is_err(), "{}", cursor);
+            match AccountCursor::check("after", Some(cursor)) {
+                Err(ApiError::Validation(errors)) => assert_eq!(errors.message(), "Invalid cursor"),
+                other => panic!("{:?} for {}", other, cursor),
+            }
+        }
+        assert!(AccountCursor::check("before", None).is_ok());
+    }
+
+    #[test]
+    fn page_sizes_are_checked_like_connection_query_does() {
+        assert!(check_page_size(None, None).is_ok());
+        assert!(check_page_size(Some(0), None).is_ok());
+        assert!(check_page_size(None, Some(20)).is_ok());
+        for (first, last) in [(Some(1), Some(1)), (Some(-1), None), (None, Some(-5))] {
+            assert!(
+                matches!(check_page_size(first, last), Err(ApiError::Validation(_))),
+                "{:?} {:?}",
+                first,
+                last
+            );
+        }
+    }
+}
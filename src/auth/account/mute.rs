@@ -0,0 +1,33 @@
+use async_graphql::Object;
+
+use crate::auth::scalar::Timestamp;
+
+/// A row of `account_muted`, `guid` is the account id and `mutetime` is in minutes
+#[derive(Clone, Debug, Default, sqlx::FromRow)]
+pub struct Mute {
+    pub guid: u64,
+    pub mutedate: u32,
+    pub mutetime: u32,
+    pub mutedby: String,
+    pub mutereason: String,
+}
+
+#[Object]
+impl Mute {
+    async fn account_id(&self) -> u64 {
+        self.guid
+    }
+    async fn mutedate(&self) -> Timestamp {
+        Timestamp::from_unix(self.mutedate as i64)
+    }
+    /// Length of the mute in minutes
+    async fn mutetime(&self) -> u32 {
+        self.mutetime
+    }
+    async fn mutedby(&self) -> String {
+        self.mutedby.clone()
+    }
+    async fn mutereason(&self) -> String {
+        self.mutereason.clone()
+    }
+}
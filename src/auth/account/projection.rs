@@ -0,0 +1,82 @@
+use std::collections::BTreeSet;
+
+use async_graphql::SelectionField;
+
+/// Columns of `account` to load, planned from the fields a query selects on `Account`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Projection {
+    /// Empty when the whole row is loaded
+    columns: BTreeSet<&'static str>,
+}
+
+/// Columns a field of `Account` reads, nested resolvers need the keys they look up by
+fn columns_of(field: &str) -> &'static [&'static str] {
+    match field {
+        "id" | "access" | "realmcharacters" | "mutes" | "bans" | "recruits" => &["id"],
+        "username" => &["username"],
+        "email" => &["email"],
+        "regMail" => &["reg_mail"],
+        "salt" => &["salt"],
+        "verifier" => &["verifier"],
+        "sessionKey" => &["session_key"],
+        "totpSecret" => &["totp_secret"],
+        "joindate" => &["joindate"],
+        "lastIp" => &["last_ip"],
+        "lastAttemptIp" => &["last_attempt_ip"],
+        "failedLogins" => &["failed_logins"],
+        "locked" => &["locked"],
+        "lockCountry" => &["lock_country"],
+        "lastLogin" => &["last_login"],
+        "online" => &["online"],
+        "expansion" => &["expansion"],
+        "os" => &["os"],
+        "isMuted" | "muteExpiresAt" => &["mutetime"],
+        "mutereason" => &["mutereason"],
+        "muteby" => &["muteby"],
+        "recruiter" => &["recruiter"],
+        "totaltime" => &["totaltime"],
+        _ => &[],
+    }
+}
+
+impl Projection {
+    /// The whole row, for lookups outside of a query like the caller's own account
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// The given SQL columns
+    pub fn of(columns: &[&'static str]) -> Self {
+        Self {
+            columns: columns.iter().copied().collect(),
+        }
+    }
+
+    /// Columns for the fields selected on an account, fragments are already flattened
+    /// by `selection_set` and aliases resolve to the field they name.
+    /// `id` is always loaded as field guards depend on it.
+    pub fn account<'a>(selection: impl Iterator<Item = SelectionField<'a>>) -> Self {
+        let mut projection = Self::of(&["id"]);
+        for field in selection {
+            projection.columns.extend(columns_of(field.name()));
+        }
+        projection
+    }
+
+    /// Adds a column the caller needs besides the selected fields, like the sort column
+    pub fn with(mut self, column: &'static str) -> Self {
+        if !self.columns.is_empty() {
+            self.columns.insert(column);
+        }
+        self
+    }
+
+    /// The column list of a `SELECT`
+    pub fn sql(&self) -> String {
+        if self.columns.is_empty() {
+            "*".to_string()
+        } else {
+            self.columns.iter().copied().collect::<Vec<_>>().join(",")
+        }
+    }
+}
@@ -1,12 +1,11 @@
 use async_graphql::Object;
-use struct_field_names_as_array::FieldNamesAsArray;
 
-#[derive(Clone, Debug, Default, sqlx::FromRow, FieldNamesAsArray)]
+#[derive(Clone, Debug, Default, sqlx::FromRow)]
 pub struct RealmCharacter {
     #[sqlx(default)]
     realmid: u64,
     #[sqlx(default)]
-    acctid: u64,
+    pub acctid: u64,
     #[sqlx(default)]
     numchars: u8,
     #[sqlx(default)]
@@ -16,13 +15,13 @@ pub struct RealmCharacter {
 #[Object]
 impl RealmCharacter {
     async fn realmid(&self) -> u64 {
-        self.realmid.clone()
+        self.realmid
     }
     async fn acctid(&self) -> u64 {
-        self.acctid.clone()
+        self.acctid
     }
     async fn numchars(&self) -> u8 {
-        self.numchars.clone()
+        self.numchars
     }
     async fn realmname(&self) -> String {
         self.realmname.clone()
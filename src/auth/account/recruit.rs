@@ -0,0 +1,27 @@
+use async_graphql::Object;
+use sqlx::types::chrono::{DateTime, Utc};
+
+use super::ID;
+use crate::auth::scalar::Timestamp;
+
+/// The public part of an account linked through Recruit-a-Friend,
+/// owners can see it without being allowed to read the account itself
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Recruit {
+    pub id: ID,
+    pub username: String,
+    pub joindate: DateTime<Utc>,
+}
+
+#[Object]
+impl Recruit {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn username(&self) -> String {
+        self.username.clone()
+    }
+    async fn joindate(&self) -> Timestamp {
+        Timestamp(self.joindate)
+    }
+}
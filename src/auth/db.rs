@@ -1,72 +1,259 @@
 use super::account::access::Access;
+use super::account::ban::{Ban, IpBan};
+use super::account::expansion::Expansion;
+use super::account::filter::{AccountCursor, AccountFilter, AccountOrder, OrderDirection};
+use super::account::mute::Mute;
+use super::account::projection::Projection;
 use super::account::realmcharacters::RealmCharacter;
+use super::account::recruit::Recruit;
 use super::account::Account;
-use futures::executor::block_on;
-use futures::lock::Mutex;
+use super::account::ID;
+use super::error::ApiError;
+use super::session::Claims;
+use super::srp;
+use super::verification::Verification;
 use log::debug;
 use log::error;
-use sqlx::error::DatabaseError;
+use sqlx::mysql::MySqlPoolOptions;
+use sqlx::types::chrono::{DateTime, Utc};
 use sqlx::MySqlPool;
 use sqlx::Row;
-use std::env;
-use std::sync::Arc;
-use wow_srp::normalized_string::NormalizedString;
-use wow_srp::server::SrpVerifier;
+use std::time::Duration;
 
-pub type Storage = Arc<Mutex<AuthDB>>;
-pub async fn get_storage() -> Storage {
-    let conn = AuthDB::new().await;
-    Storage::new(Mutex::new(conn))
+use crate::config::Config;
+
+/// `account_banned.bannedby` of bans the API places itself
+pub const BANNED_BY: &str = "acore-graphql";
+/// `account_banned.banreason` of accounts locked until their email is verified
+pub const UNVERIFIED_BAN_REASON: &str = "Email not verified";
+
+/// Columns of `Access`, the end of a pending grant is loaded along so `expiresAt` needs no query per row
+const ACCESS_COLUMNS: &str = "a.id, a.gmlevel, a.realmid, a.comment, \
+    (SELECT MAX(g.expires_at) FROM api_access_grant g \
+    WHERE g.account_id = a.id AND g.realm_id = a.realmid AND g.revoked_at IS NULL) AS expires_at";
+
+/// `MySqlPool` is shared between requests and handles concurrency itself, so resolvers use it directly
+pub type Storage = AuthDB;
+
+/// Input of `AuthDB::get_accounts_page`, `limit` accounts after `after` and before `before`.
+/// `backward` takes the ones closest to `before` instead of the ones closest to `after`.
+pub struct AccountPage<'a> {
+    pub filter: &'a AccountFilter,
+    pub order: AccountOrder,
+    pub direction: OrderDirection,
+    pub after: Option<&'a AccountCursor>,
+    pub before: Option<&'a AccountCursor>,
+    pub limit: usize,
+    pub backward: bool,
 }
-#[derive(Debug)]
-pub struct AuthDB {
-    pool: MySqlPool,
+
+/// A value bound to a placeholder of a query that is put together at runtime
+enum SqlParam {
+    Str(String),
+    U64(u64),
+    DateTime(DateTime<Utc>),
 }
-impl Default for AuthDB {
-    fn default() -> Self {
-        Self::new_sync()
+
+/// Conditions and their parameters for an `AccountFilter`, user input is only ever bound
+fn account_filter_sql(filter: &AccountFilter) -> (Vec<String>, Vec<SqlParam>) {
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+    if let Some(prefix) = &filter.username_prefix {
+        let escaped = prefix
+            .to_ascii_uppercase()
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        conditions.push("username LIKE ?".to_string());
+        params.push(SqlParam::Str(format!("{}%", escaped)));
+    }
+    if let Some(email) = &filter.email {
+        conditions.push("email = ?".to_string());
+        params.push(SqlParam::Str(email.0.clone()));
+    }
+    if let Some(last_ip) = &filter.last_ip {
+        conditions.push("last_ip = ?".to_string());
+        params.push(SqlParam::Str(last_ip.0.to_string()));
+    }
+    if let Some(online) = filter.online {
+        conditions.push(if online { "online != 0" } else { "online = 0" }.to_string());
+    }
+    if let Some(locked) = filter.locked {
+        conditions.push(if locked { "locked != 0" } else { "locked = 0" }.to_string());
+    }
+    if let Some(expansion) = filter.expansion {
+        conditions.push("expansion = ?".to_string());
+        params.push(SqlParam::U64(expansion as u64));
+    }
+    for (value, condition) in [
+        (filter.joined_after, "joindate > ?"),
+        (filter.joined_before, "joindate < ?"),
+        (filter.last_login_after, "last_login > ?"),
+        (filter.last_login_before, "last_login < ?"),
+    ] {
+        if let Some(value) = value {
+            conditions.push(condition.to_string());
+            params.push(SqlParam::DateTime(value.0));
+        }
     }
+    (conditions, params)
 }
-#[allow(dead_code)]
-impl AuthDB {
-    pub fn new_sync() -> Self {
-        block_on(Self::new())
+
+fn where_clause(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        "".to_string()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
     }
-    pub async fn new() -> Self {
-        let pool = MySqlPool::connect(&env::var("AUTH_DB").unwrap())
+}
+
+/// Input of `AuthDB::create_account`
+pub struct NewAccount<'a> {
+    pub username: &'a str,
+    pub password: &'a str,
+    pub email: &'a str,
+    pub expansion: Expansion,
+    pub recruiter: Option<ID>,
+    /// gmlevel on all realms
+    pub gmlevel: Option<u8>,
+    /// Banned until the email is verified with this token
+    pub verification: Option<&'a Verification>,
+}
+pub async fn get_storage(config: &Config) -> Storage {
+    AuthDB::new(config).await
+}
+#[derive(Clone, Debug)]
+pub struct AuthDB {
+    pool: MySqlPool,
+}
+impl AuthDB {
+    pub async fn new(config: &Config) -> Self {
+        let idle_timeout = match config.db_idle_timeout() {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        };
+        let pool = MySqlPoolOptions::new()
+            .max_connections(config.db_max_connections())
+            .min_connections(config.db_min_connections())
+            .acquire_timeout(Duration::from_secs(config.db_acquire_timeout()))
+            .idle_timeout(idle_timeout)
+            .connect(&config.auth_db())
             .await
             .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
         Self { pool }
     }
 
-    pub async fn get_accounts(&self) -> Vec<Account> {
-        match sqlx::query_as::<_, Account>("SELECT * FROM account")
-            .fetch_all(&self.pool)
-            .await
-        {
-            Ok(accs) => accs,
+    /// Keyset page of the accounts matching a filter, in the requested order
+    pub async fn get_accounts_page(
+        &self,
+        projection: &Projection,
+        page: &AccountPage<'_>,
+    ) -> Result<Vec<Account>, ApiError> {
+        // the cursor of each account is built from the sort column
+        let columns = projection.clone().with(page.order.field()).sql();
+        let (mut conditions, mut params) = account_filter_sql(page.filter);
+        let expr = page.order.sql();
+        let descending = page.direction == OrderDirection::Desc;
+        for (cursor, after) in [(page.after, true), (page.before, false)] {
+            if let Some(cursor) = cursor {
+                let cmp = if descending == after { "<" } else { ">" };
+                conditions.push(format!(
+                    "({expr} {cmp} ? OR ({expr} = ? AND id {cmp} ?))",
+                    expr = expr,
+                    cmp = cmp
+                ));
+                params.push(SqlParam::Str(cursor.value.clone()));
+                params.push(SqlParam::Str(cursor.value.clone()));
+                params.push(SqlParam::U64(cursor.id));
+            }
+        }
+        let direction = if descending != page.backward {
+            "DESC"
+        } else {
+            "ASC"
+        };
+        let sql = format!(
+            "SELECT {} FROM account{} ORDER BY {} {}, id {} LIMIT ?",
+            &columns,
+            where_clause(&conditions),
+            expr,
+            direction,
+            direction
+        );
+        let mut query = sqlx::query_as::<_, Account>(sql.as_str());
+        for param in params {
+            query = match param {
+                SqlParam::Str(v) => query.bind(v),
+                SqlParam::U64(v) => query.bind(v),
+                SqlParam::DateTime(v) => query.bind(v),
+            };
+        }
+        match query.bind(page.limit as u64).fetch_all(&self.pool).await {
+            Ok(mut accs) => {
+                if page.backward {
+                    accs.reverse();
+                }
+                Ok(accs)
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when loading accounts".to_string(),
+                ))
+            }
+        }
+    }
+    pub async fn count_accounts(&self, filter: &AccountFilter) -> Result<u64, ApiError> {
+        let (conditions, params) = account_filter_sql(filter);
+        let sql = format!("SELECT COUNT(*) FROM account{}", where_clause(&conditions));
+        let mut query = sqlx::query_scalar::<_, i64>(sql.as_str());
+        for param in params {
+            query = match param {
+                SqlParam::Str(v) => query.bind(v),
+                SqlParam::U64(v) => query.bind(v),
+                SqlParam::DateTime(v) => query.bind(v),
+            };
+        }
+        match query.fetch_one(&self.pool).await {
+            Ok(count) => Ok(count as u64),
             Err(e) => {
                 error!("{:?}", e);
-                Vec::new()
+                Err(ApiError::Database(
+                    "An error when counting accounts".to_string(),
+                ))
             }
         }
     }
-    pub async fn get_accounts_with_fields(&self, fields: &Vec<&str>) -> Vec<Account> {
-        let columns = get_columns(&Account::FIELD_NAMES_AS_ARRAY.to_vec(), fields);
-        let sql = format!("SELECT {} FROM account", &columns);
+    /// Accounts with at least one `account_access` row
+    pub async fn get_staff_with_fields(
+        &self,
+        projection: &Projection,
+    ) -> Result<Vec<Account>, ApiError> {
+        let columns = projection.sql();
+        let sql = format!(
+            "SELECT {} FROM account WHERE id IN (SELECT id FROM account_access) ORDER BY id",
+            &columns
+        );
         match sqlx::query_as::<_, Account>(sql.as_str())
             .fetch_all(&self.pool)
             .await
         {
-            Ok(accs) => accs,
+            Ok(accs) => Ok(accs),
             Err(e) => {
                 error!("{:?}", e);
-                Vec::new()
+                Err(ApiError::Database(
+                    "An error when loading staff accounts".to_string(),
+                ))
             }
         }
     }
-    pub async fn get_account_by_id(&self, id: u64, fields: &Vec<&str>) -> Result<Account, String> {
-        let columns = get_columns(&Account::FIELD_NAMES_AS_ARRAY.to_vec(), fields);
+    pub async fn get_account_by_id(
+        &self,
+        id: u64,
+        projection: &Projection,
+    ) -> Result<Account, ApiError> {
+        let columns = projection.sql();
         let sql = format!("SELECT {} FROM account where id = ?", &columns);
         match sqlx::query_as::<_, Account>(sql.as_str())
             .bind(id)
@@ -74,25 +261,88 @@ impl AuthDB {
             .await
         {
             Ok(acc) => Ok(acc),
+            Err(sqlx::Error::RowNotFound) => {
+                Err(ApiError::NotFound("Account not found".to_string()))
+            }
             Err(e) => {
                 error!("{:?}", e);
-                Err("Account not found".to_string())
+                Err(ApiError::Database(
+                    "An error when loading an account".to_string(),
+                ))
             }
         }
     }
-    pub async fn has_account_id(&self, id: u64) -> bool {
-        sqlx::query("SELECT id from account where id = ?")
+    pub async fn has_account_id(&self, id: u64) -> Result<bool, ApiError> {
+        match sqlx::query("SELECT id from account where id = ?")
             .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+        {
+            Ok(row) => Ok(row.is_some()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when checking an account".to_string(),
+                ))
+            }
+        }
+    }
+    pub async fn get_account_id_by_username(&self, username: &str) -> Result<Option<ID>, ApiError> {
+        match sqlx::query("SELECT id FROM account WHERE username = ?")
+            .bind(username.to_ascii_uppercase())
             .map(|row| {
                 let id: u64 = row.get(0);
                 id
             })
-            .fetch_one(&self.pool)
+            .fetch_optional(&self.pool)
             .await
-            .unwrap_or_default()
-            > 0
+        {
+            Ok(id) => Ok(id),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when looking up an account".to_string(),
+                ))
+            }
+        }
     }
-    pub async fn has_account_username(&self, username: &str) -> Result<bool, String> {
+    /// The account a recruit link points to, null when it was deleted since
+    pub async fn get_recruit(&self, id: ID) -> Result<Option<Recruit>, ApiError> {
+        match sqlx::query_as::<_, Recruit>(
+            "SELECT id, username, joindate FROM account WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(recruit) => Ok(recruit),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when loading the recruiter".to_string(),
+                ))
+            }
+        }
+    }
+    /// Accounts that were recruited by the given one
+    pub async fn get_recruits(&self, recruiter: ID) -> Result<Vec<Recruit>, ApiError> {
+        match sqlx::query_as::<_, Recruit>(
+            "SELECT id, username, joindate FROM account WHERE recruiter = ? ORDER BY id",
+        )
+        .bind(recruiter)
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(recruits) => Ok(recruits),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when loading recruits".to_string(),
+                ))
+            }
+        }
+    }
+    pub async fn has_account_username(&self, username: &str) -> Result<bool, ApiError> {
         match sqlx::query("SELECT id from account where username = ?")
             .bind(username)
             .map(|row| {
@@ -105,106 +355,99 @@ impl AuthDB {
             Ok(id) => Ok(id.is_some()),
             Err(e) => {
                 error!("{:#?}", e);
-                Err("An error when checking an account".to_string())
+                Err(ApiError::Database(
+                    "An error when checking an account".to_string(),
+                ))
             }
         }
     }
 
-    pub async fn create_account(
-        &self,
-        username: &str,
-        password: &str,
-        email: &str,
-    ) -> Result<u64, String> {
-        let username = NormalizedString::new(username).unwrap();
-        let filtered_email: String = email
-            .chars()
-            .filter(|c| match *c {
-                'a'..='z' | 'A'..='Z' => true,
-                '0'..='9' => true,
-                '@' | '.' | '+' | '-' => true,
-                _ => false,
-            })
-            .collect();
-        if filtered_email.len() < email.len() {
-            return Err("User email has invalida characters".into());
-        }
-        let email = filtered_email;
-        let (salt, verifier, username) = {
-            let password = NormalizedString::new(password).unwrap();
-
-            let verifier = SrpVerifier::from_username_and_password(username, password);
-            // Salt is randomly chosen and password_verifier depends on salt so we can't assert_eq
-            // Store these values in the database for future authentication
-            // let password_verifier = verifier.password_verifier();
-            let salt = verifier.salt();
-            (
-                salt.to_vec(),
-                verifier.password_verifier().to_vec(),
-                verifier.username().to_string(),
+    /// Creates the account row, its realmcharacters rows and the optional access in one transaction.
+    /// Accounts waiting for email verification are banned and get their token in the same transaction.
+    pub async fn create_account(&self, account: &NewAccount<'_>) -> Result<u64, ApiError> {
+        let (salt, verifier, username) = srp::make_verifier(account.username, account.password)?;
+        let result: Result<u64, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let account_id = sqlx::query(
+                "INSERT INTO account(username, email, reg_mail, salt, verifier, expansion, recruiter, joindate) VALUES(?, ?, ?, ?, ?, ?, ?, NOW())",
             )
-        };
-        let sql ="INSERT INTO account(username, email, salt, verifier, expansion, joindate) VALUES(?, ?, ?, ?, ?, NOW())";
-
-        match sqlx::query(sql)
-            .bind(username)
-            .bind(email)
+            .bind(&username)
+            .bind(account.email)
+            .bind(account.email)
             .bind(&salt)
             .bind(&verifier)
-            .bind(2)
-            .execute(&self.pool)
-            .await
-        {
-            Ok(result) => {
-                let new_account_id = result.last_insert_id();
+            .bind(account.expansion as u8)
+            .bind(account.recruiter.unwrap_or(0))
+            .execute(&mut tx)
+            .await?
+            .last_insert_id();
+            sqlx::query(
+                "INSERT INTO realmcharacters(realmid, acctid, numchars) SELECT id, ?, 0 FROM realmlist",
+            )
+            .bind(account_id)
+            .execute(&mut tx)
+            .await?;
+            if let Some(gmlevel) = account.gmlevel {
+                sqlx::query("INSERT INTO account_access(id, gmlevel, RealmID) VALUES(?, ?, -1)")
+                    .bind(account_id)
+                    .bind(gmlevel)
+                    .execute(&mut tx)
+                    .await?;
+            }
+            if let Some(verification) = account.verification {
+                sqlx::query(
+                    "INSERT INTO account_banned(id, bandate, unbandate, bannedby, banreason, active) VALUES(?, UNIX_TIMESTAMP(), UNIX_TIMESTAMP(), ?, ?, 1)",
+                )
+                .bind(account_id)
+                .bind(BANNED_BY)
+                .bind(UNVERIFIED_BAN_REASON)
+                .execute(&mut tx)
+                .await?;
                 sqlx::query(
-                    "INSERT INTO realmcharacters (realmid, acctid, numchars) SELECT realmlist.id, account.id, 0 FROM realmlist, account LEFT JOIN realmcharacters ON acctid=account.id WHERE acctid IS NULL"
-                    ).execute(&self.pool).await.unwrap();
-                Ok(new_account_id)
+                    "INSERT INTO api_email_verification(account_id, token_hash, expires_at) VALUES(?, ?, ?)",
+                )
+                .bind(account_id)
+                .bind(verification.token_hash())
+                .bind(verification.expires_at())
+                .execute(&mut tx)
+                .await?;
             }
-
-            Err(e) => {
-                error!("{:?}", e);
-                match e {
-                    sqlx::Error::Database(er) => {
-                        if let Some(code) = er.code() {
-                            if code.eq("23000") {
-                                return Err("Account already exist".to_string());
-                            }
-                        }
-
-                        return Err("Account cannot be created".to_string());
-                    }
-                    _ => Err("Account cannot be created".to_string()),
+            tx.commit().await?;
+            Ok(account_id)
+        }
+        .await;
+        result.map_err(|e| {
+            error!("{:?}", e);
+            match e {
+                sqlx::Error::Database(er) if er.code().as_deref() == Some("23000") => {
+                    ApiError::AlreadyExists("Account already exist".to_string())
                 }
+                _ => ApiError::Database("Account cannot be created".to_string()),
             }
-        }
+        })
     }
-    pub async fn delete_account(&self, id: u64) -> bool {
+    pub async fn delete_account(&self, id: u64) -> Result<(), ApiError> {
         match sqlx::query("DELETE FROM account WHERE id = ? LIMIT 1")
             .bind(id)
             .execute(&self.pool)
             .await
         {
-            Ok(r) => r.rows_affected() > 0,
+            Ok(r) if r.rows_affected() == 0 => {
+                Err(ApiError::NotFound("Account not found".to_string()))
+            }
+            Ok(_) => Ok(()),
             Err(e) => {
                 error!("{:?}", e);
-                false
+                Err(ApiError::Database("Account cannot be deleted".to_string()))
             }
         }
     }
-    pub async fn access_by_user_id(
-        &self,
-        user_id: u64,
-        fields: &Vec<&str>,
-    ) -> Result<Vec<Access>, String> {
-        debug!("incomming filds {:?}", &fields);
-        debug!(
-            "Access::FIELD_NAMES_AS_ARRAY {:?}",
-            &Access::FIELD_NAMES_AS_ARRAY
+    /// Access rows of one account, `Account.access` in queries goes through `AccessLoader`
+    pub async fn access_by_user_id(&self, user_id: u64) -> Result<Vec<Access>, ApiError> {
+        let sql = format!(
+            "SELECT {} FROM account_access a where a.id = ?",
+            ACCESS_COLUMNS
         );
-        let columns = get_columns(&Access::FIELD_NAMES_AS_ARRAY.to_vec(), fields);
-        let sql = format!("SELECT {} FROM account_access where id = ?", &columns);
         match sqlx::query_as::<_, Access>(sql.as_str())
             .bind(user_id)
             .fetch_all(&self.pool)
@@ -216,86 +459,1042 @@ impl AuthDB {
             }
             Err(e) => {
                 error!("{:?}", e);
-                Err(format!("Access not found for account id {}", user_id))
+                Err(ApiError::Database(
+                    "An error when loading access".to_string(),
+                ))
+            }
+        }
+    }
+    pub async fn has_realm(&self, realm_id: i32) -> Result<bool, ApiError> {
+        match sqlx::query("SELECT id FROM realmlist WHERE id = ?")
+            .bind(realm_id)
+            .fetch_optional(&self.pool)
+            .await
+        {
+            Ok(row) => Ok(row.is_some()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when looking up a realm".to_string(),
+                ))
+            }
+        }
+    }
+    /// Replaces the access of an account on a realm like `.account set gmlevel` does,
+    /// realm -1 replaces the access on every realm and gmlevel 0 only removes it.
+    /// With `grant` the new access is removed again at its `expires_at`.
+    pub async fn set_access(
+        &self,
+        account_id: ID,
+        gmlevel: u8,
+        realm_id: i32,
+        comment: Option<&str>,
+        grant: Option<(DateTime<Utc>, &str)>,
+    ) -> Result<(), ApiError> {
+        let result: Result<(), sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let (access_filter, grant_filter) = if realm_id == -1 {
+                ("id = ?", "account_id = ?")
+            } else {
+                (
+                    "id = ? AND (RealmID = ? OR RealmID = -1)",
+                    "account_id = ? AND (realm_id = ? OR realm_id = -1)",
+                )
+            };
+            for sql in [
+                format!(
+                    "UPDATE api_access_grant SET revoked_at = NOW(), revoke_reason = 'replaced' WHERE revoked_at IS NULL AND {}",
+                    grant_filter
+                ),
+                format!("DELETE FROM account_access WHERE {}", access_filter),
+            ] {
+                let mut query = sqlx::query(&sql).bind(account_id);
+                if realm_id != -1 {
+                    query = query.bind(realm_id);
+                }
+                query.execute(&mut tx).await?;
+            }
+            if gmlevel > 0 {
+                sqlx::query(
+                    "INSERT INTO account_access(id, gmlevel, RealmID, comment) VALUES(?, ?, ?, ?)",
+                )
+                .bind(account_id)
+                .bind(gmlevel)
+                .bind(realm_id)
+                .bind(comment)
+                .execute(&mut tx)
+                .await?;
+                if let Some((expires_at, granted_by)) = grant {
+                    sqlx::query(
+                        "INSERT INTO api_access_grant(account_id, realm_id, gmlevel, granted_by, expires_at) VALUES(?, ?, ?, ?, ?)",
+                    )
+                    .bind(account_id)
+                    .bind(realm_id)
+                    .bind(gmlevel)
+                    .bind(granted_by)
+                    .bind(expires_at)
+                    .execute(&mut tx)
+                    .await?;
+                }
+            }
+            tx.commit().await
+        }
+        .await;
+        result.map_err(|e| {
+            error!("{:?}", e);
+            ApiError::Database("Access cannot be set".to_string())
+        })
+    }
+    pub async fn delete_access(&self, account_id: ID, realm_id: i32) -> Result<bool, ApiError> {
+        let result: Result<bool, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(
+                "UPDATE api_access_grant SET revoked_at = NOW(), revoke_reason = 'revoked' WHERE revoked_at IS NULL AND account_id = ? AND realm_id = ?",
+            )
+            .bind(account_id)
+            .bind(realm_id)
+            .execute(&mut tx)
+            .await?;
+            let deleted = sqlx::query("DELETE FROM account_access WHERE id = ? AND RealmID = ?")
+                .bind(account_id)
+                .bind(realm_id)
+                .execute(&mut tx)
+                .await?
+                .rows_affected();
+            tx.commit().await?;
+            Ok(deleted > 0)
+        }
+        .await;
+        result.map_err(|e| {
+            error!("{:?}", e);
+            ApiError::Database("Access cannot be revoked".to_string())
+        })
+    }
+    /// Removes the access rows of grants that ran out and marks the grants as expired,
+    /// returns the account id and realm of every removed grant
+    pub async fn expire_access_grants(&self) -> Result<Vec<(ID, i32)>, ApiError> {
+        let result: Result<Vec<(ID, i32)>, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let grants = sqlx::query(
+                "SELECT id, account_id, realm_id, gmlevel FROM api_access_grant WHERE revoked_at IS NULL AND expires_at <= ? FOR UPDATE",
+            )
+            .bind(Utc::now())
+            .map(|row| {
+                let id: u32 = row.get(0);
+                let account_id: u64 = row.get(1);
+                let realm_id: i32 = row.get(2);
+                let gmlevel: u8 = row.get(3);
+                (id, account_id, realm_id, gmlevel)
+            })
+            .fetch_all(&mut tx)
+            .await?;
+            for (id, account_id, realm_id, gmlevel) in &grants {
+                sqlx::query("DELETE FROM account_access WHERE id = ? AND RealmID = ? AND gmlevel = ?")
+                    .bind(account_id)
+                    .bind(realm_id)
+                    .bind(gmlevel)
+                    .execute(&mut tx)
+                    .await?;
+                sqlx::query(
+                    "UPDATE api_access_grant SET revoked_at = NOW(), revoke_reason = 'expired' WHERE id = ?",
+                )
+                .bind(id)
+                .execute(&mut tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok(grants
+                .into_iter()
+                .map(|(_, account_id, realm_id, _)| (account_id, realm_id))
+                .collect())
+        }
+        .await;
+        result.map_err(|e| {
+            error!("{:?}", e);
+            ApiError::Database("Expired access grants cannot be removed".to_string())
+        })
+    }
+    /// Access rows of several accounts in one query, for `AccessLoader`
+    pub async fn access_by_user_ids(&self, user_ids: &[ID]) -> Result<Vec<Access>, ApiError> {
+        let sql = format!(
+            "SELECT {} FROM account_access a WHERE a.id IN ({})",
+            ACCESS_COLUMNS,
+            placeholders(user_ids.len())
+        );
+        let mut query = sqlx::query_as::<_, Access>(sql.as_str());
+        for id in user_ids {
+            query = query.bind(id);
+        }
+        match query.fetch_all(&self.pool).await {
+            Ok(res) => Ok(res),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when loading access".to_string(),
+                ))
             }
         }
     }
-    pub async fn realmcharacters_by_user_id(
+    /// Realmcharacters rows of several accounts in one query, for `RealmCharactersLoader`
+    pub async fn realmcharacters_by_user_ids(
         &self,
-        user_id: u64,
-    ) -> Result<Vec<RealmCharacter>, String> {
+        user_ids: &[ID],
+    ) -> Result<Vec<RealmCharacter>, ApiError> {
         let sql = format!(
-            r#"SELECT 
-        rc.realmid as realmid, 
-        rc.acctid as acctid,
-        rc.numchars as numchars, 
-        rl.name as realmname 
-        FROM realmcharacters rc  
-        JOIN realmlist rl  ON rl.id = rc.realmid 
-        WHERE rc.acctid = ?"#
+            "SELECT rc.realmid as realmid, rc.acctid as acctid, rc.numchars as numchars, rl.name as realmname \
+            FROM realmcharacters rc JOIN realmlist rl ON rl.id = rc.realmid WHERE rc.acctid IN ({})",
+            placeholders(user_ids.len())
         );
-        match sqlx::query_as::<_, RealmCharacter>(sql.as_str())
-            .bind(user_id)
-            .fetch_all(&self.pool)
+        let mut query = sqlx::query_as::<_, RealmCharacter>(sql.as_str());
+        for id in user_ids {
+            query = query.bind(id);
+        }
+        match query.fetch_all(&self.pool).await {
+            Ok(res) => Ok(res),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when loading realm characters".to_string(),
+                ))
+            }
+        }
+    }
+    /// Returns the account id and whether the password matches the stored SRP6 verifier,
+    /// `None` when there is no account with the username
+    pub async fn check_credentials(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<(ID, bool)>, ApiError> {
+        let username = username.to_ascii_uppercase();
+        match sqlx::query("SELECT id, salt, verifier FROM account WHERE username = ?")
+            .bind(&username)
+            .map(|row| {
+                let id: u64 = row.get(0);
+                let salt: Vec<u8> = row.get(1);
+                let verifier: Vec<u8> = row.get(2);
+                (id, salt, verifier)
+            })
+            .fetch_optional(&self.pool)
             .await
         {
-            Ok(res) => Ok(res),
+            Ok(Some((id, salt, verifier))) => Ok(Some((
+                id,
+                srp::verify_password(&username, password, &salt, &verifier)?,
+            ))),
+            Ok(None) => Ok(None),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when checking credentials".to_string(),
+                ))
+            }
+        }
+    }
+
+    pub async fn create_session(&self, claims: &Claims) -> Result<(), ApiError> {
+        match sqlx::query("INSERT INTO api_session(id, account_id, expires_at) VALUES(?, ?, ?)")
+            .bind(&claims.jti)
+            .bind(claims.sub)
+            .bind(claims.expires_at())
+            .execute(&self.pool)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Session cannot be created".to_string()))
+            }
+        }
+    }
+    pub async fn has_session(&self, claims: &Claims) -> Result<bool, ApiError> {
+        match sqlx::query(
+            "SELECT id FROM api_session WHERE id = ? AND account_id = ? AND expires_at > ?",
+        )
+        .bind(&claims.jti)
+        .bind(claims.sub)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(row) => Ok(row.is_some()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when checking a session".to_string(),
+                ))
+            }
+        }
+    }
+    pub async fn delete_session(&self, jti: &str) -> Result<bool, ApiError> {
+        match sqlx::query("DELETE FROM api_session WHERE id = ?")
+            .bind(jti)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(r) => Ok(r.rows_affected() > 0),
             Err(e) => {
                 error!("{:?}", e);
-                Err(format!("No characters found for the user #{}", user_id))
+                Err(ApiError::Database("Session cannot be deleted".to_string()))
+            }
+        }
+    }
+    pub async fn delete_expired_sessions(&self) -> Result<u64, ApiError> {
+        match sqlx::query("DELETE FROM api_session WHERE expires_at <= ?")
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await
+        {
+            Ok(r) => Ok(r.rows_affected()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "Expired sessions cannot be deleted".to_string(),
+                ))
             }
         }
     }
 
-    pub async fn set_username_password(
+    pub async fn get_totp_secret(&self, account_id: ID) -> Result<Option<Vec<u8>>, ApiError> {
+        match sqlx::query("SELECT totp_secret FROM account WHERE id = ?")
+            .bind(account_id)
+            .map(|row| {
+                let secret: Option<Vec<u8>> = row.get(0);
+                secret
+            })
+            .fetch_one(&self.pool)
+            .await
+        {
+            Ok(secret) => Ok(secret),
+            Err(sqlx::Error::RowNotFound) => {
+                Err(ApiError::NotFound("Account not found".to_string()))
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when loading a TOTP secret".to_string(),
+                ))
+            }
+        }
+    }
+    pub async fn set_totp_secret(
         &self,
-        username: String,
-        password: String,
-    ) -> Result<bool, String> {
-        let username = NormalizedString::new(username).unwrap();
-        let (salt, verifier, username) = {
-            let password = NormalizedString::new(password).unwrap();
+        account_id: ID,
+        secret: Option<&[u8]>,
+    ) -> Result<bool, ApiError> {
+        match sqlx::query("UPDATE account SET totp_secret = ? WHERE id = ?")
+            .bind(secret)
+            .bind(account_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(r) => Ok(r.rows_affected() > 0),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when setting a TOTP secret".to_string(),
+                ))
+            }
+        }
+    }
+    pub async fn get_totp_enrollment(&self, account_id: ID) -> Result<Option<Vec<u8>>, ApiError> {
+        match sqlx::query("SELECT secret FROM api_totp_enrollment WHERE account_id = ?")
+            .bind(account_id)
+            .map(|row| {
+                let secret: Vec<u8> = row.get(0);
+                secret
+            })
+            .fetch_optional(&self.pool)
+            .await
+        {
+            Ok(secret) => Ok(secret),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when loading a TOTP enrollment".to_string(),
+                ))
+            }
+        }
+    }
+    pub async fn set_totp_enrollment(&self, account_id: ID, secret: &[u8]) -> Result<(), ApiError> {
+        match sqlx::query(
+            "REPLACE INTO api_totp_enrollment(account_id, secret, created_at) VALUES(?, ?, NOW())",
+        )
+        .bind(account_id)
+        .bind(secret)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when saving a TOTP enrollment".to_string(),
+                ))
+            }
+        }
+    }
+    pub async fn delete_totp_enrollment(&self, account_id: ID) -> Result<bool, ApiError> {
+        match sqlx::query("DELETE FROM api_totp_enrollment WHERE account_id = ?")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(r) => Ok(r.rows_affected() > 0),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when deleting a TOTP enrollment".to_string(),
+                ))
+            }
+        }
+    }
 
-            let verifier = SrpVerifier::from_username_and_password(username, password);
-            // Salt is randomly chosen and password_verifier depends on salt so we can't assert_eq
-            // Store these values in the database for future authentication
-            // let password_verifier = verifier.password_verifier();
-            let salt = verifier.salt();
-            (
-                salt.to_vec(),
-                verifier.password_verifier().to_vec(),
-                verifier.username().to_string(),
-            )
+    /// Sets the password of an account, a new salt is generated
+    pub async fn set_account_password(
+        &self,
+        account_id: ID,
+        password: &str,
+    ) -> Result<bool, ApiError> {
+        let username = match sqlx::query("SELECT username FROM account WHERE id = ?")
+            .bind(account_id)
+            .map(|row| {
+                let username: String = row.get(0);
+                username
+            })
+            .fetch_one(&self.pool)
+            .await
+        {
+            Ok(username) => username,
+            Err(sqlx::Error::RowNotFound) => {
+                return Err(ApiError::NotFound("Account not found".to_string()));
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(ApiError::Database(
+                    "An error when setting an account password".to_string(),
+                ));
+            }
         };
-        let sql = "UPDATE account SET salt = ?, verifier = ? where username = ?";
-        match sqlx::query(sql)
+        let (salt, verifier, _) = srp::make_verifier(&username, password)?;
+        match sqlx::query("UPDATE account SET salt = ?, verifier = ? WHERE id = ?")
             .bind(salt)
             .bind(verifier)
-            .bind(username)
+            .bind(account_id)
             .execute(&self.pool)
             .await
         {
             Ok(r) => Ok(r.rows_affected() > 0),
             Err(e) => {
                 error!("{:?}", e);
-                Err("An error when setting an account password".to_string())
+                Err(ApiError::Database(
+                    "An error when setting an account password".to_string(),
+                ))
+            }
+        }
+    }
+    /// Revokes all sessions of an account, but the one given in `except`
+    pub async fn delete_account_sessions(
+        &self,
+        account_id: ID,
+        except: Option<&str>,
+    ) -> Result<u64, ApiError> {
+        match sqlx::query("DELETE FROM api_session WHERE account_id = ? AND id <> ?")
+            .bind(account_id)
+            .bind(except.unwrap_or_default())
+            .execute(&self.pool)
+            .await
+        {
+            Ok(r) => Ok(r.rows_affected()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Sessions cannot be deleted".to_string()))
+            }
+        }
+    }
+
+    /// Ids and usernames of the accounts registered with an email
+    pub async fn accounts_by_email(&self, email: &str) -> Result<Vec<(ID, String)>, ApiError> {
+        match sqlx::query("SELECT id, username FROM account WHERE email = ?")
+            .bind(email)
+            .map(|row| {
+                let id: u64 = row.get(0);
+                let username: String = row.get(1);
+                (id, username)
+            })
+            .fetch_all(&self.pool)
+            .await
+        {
+            Ok(accounts) => Ok(accounts),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when looking up accounts".to_string(),
+                ))
+            }
+        }
+    }
+    pub async fn create_password_reset(
+        &self,
+        token_hash: &str,
+        account_id: ID,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        match sqlx::query(
+            "INSERT INTO api_password_reset(token_hash, account_id, expires_at) VALUES(?, ?, ?)",
+        )
+        .bind(token_hash)
+        .bind(account_id)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "Password reset cannot be created".to_string(),
+                ))
+            }
+        }
+    }
+    /// Marks a valid token as used and returns the account it was issued for
+    pub async fn use_password_reset(&self, token_hash: &str) -> Result<Option<ID>, ApiError> {
+        let now = Utc::now();
+        let used = sqlx::query(
+            "UPDATE api_password_reset SET used_at = ? WHERE token_hash = ? AND used_at IS NULL AND expires_at > ?",
+        )
+        .bind(now)
+        .bind(token_hash)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("{:?}", e);
+            ApiError::Database("An error when using a password reset".to_string())
+        })?;
+        if used.rows_affected() == 0 {
+            return Ok(None);
+        }
+        match sqlx::query("SELECT account_id FROM api_password_reset WHERE token_hash = ?")
+            .bind(token_hash)
+            .map(|row| {
+                let id: u64 = row.get(0);
+                id
+            })
+            .fetch_optional(&self.pool)
+            .await
+        {
+            Ok(id) => Ok(id),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when using a password reset".to_string(),
+                ))
+            }
+        }
+    }
+
+    /// Creates or replaces the verification token of an account
+    pub async fn create_email_verification(
+        &self,
+        account_id: ID,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        match sqlx::query(
+            "REPLACE INTO api_email_verification(account_id, token_hash, expires_at) VALUES(?, ?, ?)",
+        )
+        .bind(account_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Email verification cannot be created".to_string()))
+            }
+        }
+    }
+    /// Id and `reg_mail` of an account still waiting for its email to be verified
+    pub async fn pending_email_verification(
+        &self,
+        username: &str,
+    ) -> Result<Option<(ID, String)>, ApiError> {
+        match sqlx::query(
+            "SELECT a.id, a.reg_mail FROM account a JOIN api_email_verification v ON v.account_id = a.id WHERE a.username = ?",
+        )
+        .bind(username.to_ascii_uppercase())
+        .map(|row| {
+            let id: u64 = row.get(0);
+            let reg_mail: String = row.get(1);
+            (id, reg_mail)
+        })
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(pending) => Ok(pending),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("An error when looking up an email verification".to_string()))
+            }
+        }
+    }
+    pub async fn has_pending_email_verification(&self, account_id: ID) -> Result<bool, ApiError> {
+        match sqlx::query("SELECT account_id FROM api_email_verification WHERE account_id = ?")
+            .bind(account_id)
+            .fetch_optional(&self.pool)
+            .await
+        {
+            Ok(row) => Ok(row.is_some()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when looking up an email verification".to_string(),
+                ))
+            }
+        }
+    }
+    /// Unlocks the account of a valid token and returns its id
+    pub async fn verify_email(&self, token_hash: &str) -> Result<Option<ID>, ApiError> {
+        let result: Result<Option<ID>, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let account_id = sqlx::query(
+                "SELECT account_id FROM api_email_verification WHERE token_hash = ? AND expires_at > ? FOR UPDATE",
+            )
+            .bind(token_hash)
+            .bind(Utc::now())
+            .map(|row| {
+                let id: u64 = row.get(0);
+                id
+            })
+            .fetch_optional(&mut tx)
+            .await?;
+            if let Some(account_id) = account_id {
+                sqlx::query("DELETE FROM api_email_verification WHERE account_id = ?")
+                    .bind(account_id)
+                    .execute(&mut tx)
+                    .await?;
+                sqlx::query(
+                    "DELETE FROM account_banned WHERE id = ? AND bannedby = ? AND banreason = ?",
+                )
+                .bind(account_id)
+                .bind(BANNED_BY)
+                .bind(UNVERIFIED_BAN_REASON)
+                .execute(&mut tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok(account_id)
+        }
+        .await;
+        result.map_err(|e| {
+            error!("{:?}", e);
+            ApiError::Database("An error when verifying an email".to_string())
+        })
+    }
+    /// Deletes accounts registered before `joined_before` that never verified their email,
+    /// together with every row that refers to them
+    pub async fn delete_unverified_accounts(
+        &self,
+        joined_before: DateTime<Utc>,
+    ) -> Result<u64, ApiError> {
+        let result: Result<u64, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let ids = sqlx::query(
+                "SELECT a.id FROM account a JOIN api_email_verification v ON v.account_id = a.id WHERE a.joindate < ? FOR UPDATE",
+            )
+            .bind(joined_before)
+            .map(|row| {
+                let id: u64 = row.get(0);
+                id
+            })
+            .fetch_all(&mut tx)
+            .await?;
+            for id in &ids {
+                // staff may have granted access or muted the account before it was verified
+                for sql in [
+                    "DELETE FROM api_email_verification WHERE account_id = ?",
+                    "DELETE FROM api_session WHERE account_id = ?",
+                    "DELETE FROM api_password_reset WHERE account_id = ?",
+                    "DELETE FROM api_totp_enrollment WHERE account_id = ?",
+                    "DELETE FROM api_access_grant WHERE account_id = ?",
+                    "DELETE FROM account_access WHERE id = ?",
+                    "DELETE FROM account_muted WHERE guid = ?",
+                    "DELETE FROM account_banned WHERE id = ?",
+                    "DELETE FROM realmcharacters WHERE acctid = ?",
+                    "DELETE FROM account WHERE id = ?",
+                ] {
+                    sqlx::query(sql).bind(id).execute(&mut tx).await?;
+                }
+            }
+            tx.commit().await?;
+            Ok(ids.len() as u64)
+        }
+        .await;
+        result.map_err(|e| {
+            error!("{:?}", e);
+            ApiError::Database("Unverified accounts cannot be deleted".to_string())
+        })
+    }
+
+    /// Increments `failed_logins` of an account and returns the new value
+    pub async fn record_failed_login(&self, account_id: ID, ip: &str) -> Result<u32, ApiError> {
+        let result: Result<u32, sqlx::Error> = async {
+            sqlx::query(
+                "UPDATE account SET failed_logins = failed_logins + 1, last_attempt_ip = ? WHERE id = ?",
+            )
+            .bind(ip)
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+            sqlx::query("SELECT failed_logins FROM account WHERE id = ?")
+                .bind(account_id)
+                .map(|row| {
+                    let failed_logins: u32 = row.get(0);
+                    failed_logins
+                })
+                .fetch_one(&self.pool)
+                .await
+        }
+        .await;
+        result.map_err(|e| {
+            error!("{:?}", e);
+            ApiError::Database("An error when recording a failed login".to_string())
+        })
+    }
+    pub async fn reset_failed_logins(&self, account_id: ID, ip: &str) -> Result<(), ApiError> {
+        match sqlx::query("UPDATE account SET failed_logins = 0, last_attempt_ip = ? WHERE id = ?")
+            .bind(ip)
+            .bind(account_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when resetting failed logins".to_string(),
+                ))
+            }
+        }
+    }
+    /// Bans an account for `duration` seconds, 0 bans it permanently
+    pub async fn ban_account(
+        &self,
+        account_id: ID,
+        duration: u32,
+        banned_by: &str,
+        reason: &str,
+    ) -> Result<(), ApiError> {
+        match sqlx::query(
+            "INSERT INTO account_banned(id, bandate, unbandate, bannedby, banreason, active) VALUES(?, UNIX_TIMESTAMP(), UNIX_TIMESTAMP() + ?, ?, ?, 1)",
+        )
+        .bind(account_id)
+        .bind(duration)
+        .bind(banned_by)
+        .bind(reason)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Account cannot be banned".to_string()))
+            }
+        }
+    }
+    /// Bans an ip for `duration` seconds, 0 bans it permanently
+    pub async fn ban_ip(
+        &self,
+        ip: &str,
+        duration: u32,
+        banned_by: &str,
+        reason: &str,
+    ) -> Result<(), ApiError> {
+        match sqlx::query(
+            "INSERT INTO ip_banned(ip, bandate, unbandate, bannedby, banreason) VALUES(?, UNIX_TIMESTAMP(), UNIX_TIMESTAMP() + ?, ?, ?)",
+        )
+        .bind(ip)
+        .bind(duration)
+        .bind(banned_by)
+        .bind(reason)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Ip cannot be banned".to_string()))
+            }
+        }
+    }
+    /// Lifts the active bans of an account, they are kept as history like `.unban account` does
+    pub async fn unban_account(&self, account_id: ID) -> Result<bool, ApiError> {
+        match sqlx::query("UPDATE account_banned SET active = 0 WHERE id = ? AND active = 1")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(r) => Ok(r.rows_affected() > 0),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Account cannot be unbanned".to_string()))
+            }
+        }
+    }
+    /// `ip_banned` has no active flag, so bans of the ip are deleted like `.unban ip` does
+    pub async fn unban_ip(&self, ip: &str) -> Result<bool, ApiError> {
+        match sqlx::query("DELETE FROM ip_banned WHERE ip = ?")
+            .bind(ip)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(r) => Ok(r.rows_affected() > 0),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Ip cannot be unbanned".to_string()))
+            }
+        }
+    }
+    /// Bans of one or all accounts, newest first
+    pub async fn account_bans(
+        &self,
+        account_id: Option<ID>,
+        active_only: bool,
+    ) -> Result<Vec<Ban>, ApiError> {
+        let mut sql = "SELECT id, bandate, unbandate, bannedby, banreason, active FROM account_banned WHERE 1 = 1".to_string();
+        if account_id.is_some() {
+            sql.push_str(" AND id = ?");
+        }
+        if active_only {
+            sql.push_str(
+                " AND active = 1 AND (unbandate > UNIX_TIMESTAMP() OR unbandate = bandate)",
+            );
+        }
+        sql.push_str(" ORDER BY bandate DESC");
+        let mut query = sqlx::query_as::<_, Ban>(sql.as_str());
+        if let Some(account_id) = account_id {
+            query = query.bind(account_id);
+        }
+        match query.fetch_all(&self.pool).await {
+            Ok(bans) => Ok(bans),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when loading account bans".to_string(),
+                ))
+            }
+        }
+    }
+    /// Bans of one or all ips, newest first
+    pub async fn ip_bans(
+        &self,
+        ip: Option<&str>,
+        active_only: bool,
+    ) -> Result<Vec<IpBan>, ApiError> {
+        let mut sql =
+            "SELECT ip, bandate, unbandate, bannedby, banreason FROM ip_banned WHERE 1 = 1"
+                .to_string();
+        if ip.is_some() {
+            sql.push_str(" AND ip = ?");
+        }
+        if active_only {
+            sql.push_str(" AND (unbandate > UNIX_TIMESTAMP() OR unbandate = bandate)");
+        }
+        sql.push_str(" ORDER BY bandate DESC");
+        let mut query = sqlx::query_as::<_, IpBan>(sql.as_str());
+        if let Some(ip) = ip {
+            query = query.bind(ip);
+        }
+        match query.fetch_all(&self.pool).await {
+            Ok(bans) => Ok(bans),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database(
+                    "An error when loading ip bans".to_string(),
+                ))
+            }
+        }
+    }
+    pub async fn set_expansion(
+        &self,
+        account_id: ID,
+        expansion: Expansion,
+    ) -> Result<bool, ApiError> {
+        match sqlx::query("UPDATE account SET expansion = ? WHERE id = ?")
+            .bind(expansion as u8)
+            .bind(account_id)
+            .execute(&self.pool)
+            .await
+        {
+            // an unchanged expansion does not count as affected row
+            Ok(r) if r.rows_affected() == 0 => {
+                if self.has_account_id(account_id).await? {
+                    Ok(true)
+                } else {
+                    Err(ApiError::NotFound("Account not found".to_string()))
+                }
+            }
+            Ok(_) => Ok(true),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Expansion cannot be set".to_string()))
+            }
+        }
+    }
+    /// Locks an account to an ip like `.account lock ip on`, authserver compares it with `last_ip`
+    pub async fn lock_account_to_ip(&self, account_id: ID, ip: &str) -> Result<(), ApiError> {
+        match sqlx::query("UPDATE account SET locked = 1, last_ip = ? WHERE id = ?")
+            .bind(ip)
+            .bind(account_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Account cannot be locked".to_string()))
+            }
+        }
+    }
+    pub async fn lock_account_to_country(
+        &self,
+        account_id: ID,
+        country: &str,
+    ) -> Result<(), ApiError> {
+        match sqlx::query("UPDATE account SET lock_country = ? WHERE id = ?")
+            .bind(country)
+            .bind(account_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Account cannot be locked".to_string()))
+            }
+        }
+    }
+    /// Removes the ip and the country lock, `00` is the `lock_country` of unlocked accounts
+    pub async fn clear_account_lock(&self, account_id: ID) -> Result<bool, ApiError> {
+        match sqlx::query(
+            "UPDATE account SET locked = 0, lock_country = '00' WHERE id = ? AND (locked != 0 OR lock_country != '00')",
+        )
+        .bind(account_id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(r) => Ok(r.rows_affected() > 0),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Account lock cannot be cleared".to_string()))
+            }
+        }
+    }
+    /// Sets the mute of an account and records it in `account_muted`.
+    /// `mutetime` is stored as is, see `MutationRoot::mute_account`.
+    pub async fn mute_account(
+        &self,
+        account_id: ID,
+        mutetime: i64,
+        minutes: u32,
+        muted_by: &str,
+        reason: &str,
+    ) -> Result<(), ApiError> {
+        let result: Result<(), sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query("UPDATE account SET mutetime = ?, mutereason = ?, muteby = ? WHERE id = ?")
+                .bind(mutetime)
+                .bind(reason)
+                .bind(muted_by)
+                .bind(account_id)
+                .execute(&mut tx)
+                .await?;
+            sqlx::query(
+                "INSERT INTO account_muted(guid, mutedate, mutetime, mutedby, mutereason) VALUES(?, UNIX_TIMESTAMP(), ?, ?, ?)",
+            )
+            .bind(account_id)
+            .bind(minutes)
+            .bind(muted_by)
+            .bind(reason)
+            .execute(&mut tx)
+            .await?;
+            tx.commit().await
+        }
+        .await;
+        result.map_err(|e| {
+            error!("{:?}", e);
+            ApiError::Database("Account cannot be muted".to_string())
+        })
+    }
+    pub async fn unmute_account(&self, account_id: ID) -> Result<bool, ApiError> {
+        match sqlx::query(
+            "UPDATE account SET mutetime = 0, mutereason = '', muteby = '' WHERE id = ? AND mutetime != 0",
+        )
+        .bind(account_id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(r) => Ok(r.rows_affected() > 0),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("Account cannot be unmuted".to_string()))
+            }
+        }
+    }
+    /// Mute history of an account, newest first
+    pub async fn account_mutes(&self, account_id: ID) -> Result<Vec<Mute>, ApiError> {
+        match sqlx::query_as::<_, Mute>(
+            "SELECT guid, mutedate, mutetime, mutedby, mutereason FROM account_muted WHERE guid = ? ORDER BY mutedate DESC",
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(mutes) => Ok(mutes),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("An error when loading account mutes".to_string()))
+            }
+        }
+    }
+    /// Active bans are the ones that did not end yet or are permanent (`bandate = unbandate`)
+    pub async fn is_account_banned(&self, account_id: ID) -> Result<bool, ApiError> {
+        match sqlx::query(
+            "SELECT id FROM account_banned WHERE id = ? AND active = 1 AND (unbandate > UNIX_TIMESTAMP() OR unbandate = bandate) LIMIT 1",
+        )
+        .bind(account_id)
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(row) => Ok(row.is_some()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("An error when checking account bans".to_string()))
+            }
+        }
+    }
+    pub async fn is_ip_banned(&self, ip: &str) -> Result<bool, ApiError> {
+        match sqlx::query(
+            "SELECT ip FROM ip_banned WHERE ip = ? AND (unbandate > UNIX_TIMESTAMP() OR unbandate = bandate) LIMIT 1",
+        )
+        .bind(ip)
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(row) => Ok(row.is_some()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(ApiError::Database("An error when checking ip bans".to_string()))
             }
         }
     }
 }
 
-fn get_columns(struct_fields: &Vec<&str>, fields: &Vec<&str>) -> String {
-    if fields.len() > 0 {
-        use std::collections::HashSet;
-        let known_fields = HashSet::<&&str>::from_iter(struct_fields);
-        let search_fields = HashSet::<&&str>::from_iter(fields);
-        let intersection: Vec<&str> = known_fields
-            .intersection(&search_fields)
-            .map(|f| **f)
-            .collect();
-        if intersection.len() > 0 {
-            return intersection.join(",");
-        }
-    }
-    "*".to_string()
+/// `?, ?, ...` for binding a list to an `IN` clause
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
 }
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextRequest, NextSubscribe,
+};
+use async_graphql::futures_util::stream::{BoxStream, StreamExt};
+use async_graphql::{async_trait, Error, ErrorExtensions, Response};
+
+use super::validation::ValidationErrors;
+
+/// Errors of the API, each one is sent with a stable `extensions.code` clients can branch on
+#[derive(Clone, Debug)]
+pub enum ApiError {
+    /// The account, realm, token or other record does not exist
+    NotFound(String),
+    /// The record to create exists already or the change is in place already
+    AlreadyExists(String),
+    /// Invalid arguments, `extensions.fields` names each of them
+    Validation(ValidationErrors),
+    /// No session or the credentials are wrong
+    Unauthenticated(String),
+    /// The caller is not allowed to do this
+    Forbidden(String),
+    /// Too many attempts from the client address
+    RateLimited(String),
+    /// A query failed, the cause is only logged
+    Database(String),
+    /// Any other failure on the server side, like sending a mail
+    Internal(String),
+}
+
+impl ApiError {
+    /// An invalid argument, `code` is one of the codes of `ValidationErrors`
+    pub fn invalid(field: &'static str, code: &'static str, message: impl Into<String>) -> Self {
+        Self::Validation(ValidationErrors::single(field, code, message))
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "NOT_FOUND",
+            ApiError::AlreadyExists(_) => "ALREADY_EXISTS",
+            ApiError::Validation(_) => "VALIDATION",
+            ApiError::Unauthenticated(_) => "UNAUTHENTICATED",
+            ApiError::Forbidden(_) => "FORBIDDEN",
+            ApiError::RateLimited(_) => "RATE_LIMITED",
+            ApiError::Database(_) => "DATABASE",
+            ApiError::Internal(_) => "INTERNAL",
+        }
+    }
+
+    pub fn forbidden() -> Self {
+        Self::Forbidden("Forbidden".to_string())
+    }
+
+    pub fn rate_limited() -> Self {
+        Self::RateLimited("Too many attempts, try again later".to_string())
+    }
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(errors: ValidationErrors) -> Self {
+        Self::Validation(errors)
+    }
+}
+
+impl From<ApiError> for Error {
+    fn from(error: ApiError) -> Self {
+        let code = error.code();
+        let error = match error {
+            ApiError::Validation(errors) => Error::from(errors),
+            ApiError::NotFound(message)
+            | ApiError::AlreadyExists(message)
+            | ApiError::Unauthenticated(message)
+            | ApiError::Forbidden(message)
+            | ApiError::RateLimited(message)
+            | ApiError::Database(message)
+            | ApiError::Internal(message) => Error::new(message),
+        };
+        error.extend_with(|_, ext| ext.set("code", code))
+    }
+}
+
+/// Gives the errors async-graphql raises itself the `VALIDATION` code, so every error has one.
+/// They are all caused by the request, like a malformed query or an argument a scalar rejects.
+pub struct ErrorCodes;
+
+impl ErrorCodes {
+    fn tag(mut response: Response) -> Response {
+        let code = ApiError::Validation(ValidationErrors::default()).code();
+        for error in response.errors.iter_mut() {
+            let extensions = error.extensions.get_or_insert_with(Default::default);
+            if extensions.get("code").is_none() {
+                extensions.set("code", code);
+            }
+        }
+        response
+    }
+}
+
+impl ExtensionFactory for ErrorCodes {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ErrorCodes)
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for ErrorCodes {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        Self::tag(next.run(ctx).await)
+    }
+
+    fn subscribe<'s>(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        stream: BoxStream<'s, Response>,
+        next: NextSubscribe<'_>,
+    ) -> BoxStream<'s, Response> {
+        next.run(ctx, stream).map(Self::tag).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema, Value};
+
+    use super::*;
+    use crate::auth::scalar::Email;
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn echo(&self, email: Email) -> String {
+            email.0
+        }
+        async fn missing(&self) -> Result<bool, ApiError> {
+            Err(ApiError::NotFound("Account not found".to_string()))
+        }
+    }
+
+    async fn codes(query: &str) -> Vec<Option<Value>> {
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .extension(ErrorCodes)
+            .finish();
+        schema
+            .execute(query)
+            .await
+            .errors
+            .into_iter()
+            .map(|e| e.extensions.and_then(|ext| ext.get("code").cloned()))
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn rejected_scalars_get_the_validation_code() {
+        assert_eq!(
+            codes(r#"{ echo(email: "not an email") }"#).await,
+            [Some(Value::from("VALIDATION"))]
+        );
+        assert_eq!(
+            codes("{ unknown }").await,
+            [Some(Value::from("VALIDATION"))]
+        );
+    }
+
+    #[actix_web::test]
+    async fn api_errors_keep_their_code() {
+        assert_eq!(codes("{ missing }").await, [Some(Value::from("NOT_FOUND"))]);
+        assert!(codes(r#"{ echo(email: "player@example.com") }"#)
+            .await
+            .is_empty());
+    }
+}
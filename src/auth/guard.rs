@@ -0,0 +1,164 @@
+use std::net::IpAddr;
+
+use async_graphql::{async_trait, Context, Guard, Result};
+
+use crate::config::Config;
+
+use super::account::{access::Access, projection::Projection, Account, ID};
+use super::db::Storage;
+use super::error::ApiError;
+use super::session::{decode_token, Claims};
+
+/// Security levels of `account_access.gmlevel` as AzerothCore defines them in `AccountTypes`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Player = 0,
+    Moderator = 1,
+    GameMaster = 2,
+    Administrator = 3,
+}
+
+impl From<u8> for Role {
+    fn from(gmlevel: u8) -> Self {
+        match gmlevel {
+            0 => Role::Player,
+            1 => Role::Moderator,
+            2 => Role::GameMaster,
+            // SEC_CONSOLE and anything above it
+            _ => Role::Administrator,
+        }
+    }
+}
+
+/// The authenticated account of a request
+#[derive(Clone, Debug)]
+pub struct Caller {
+    pub account: Account,
+    pub access: Vec<Access>,
+    pub claims: Claims,
+}
+
+impl Caller {
+    /// Resolves a session token into the account it was issued for together with its access rows
+    pub async fn from_token(db: &Storage, config: &Config, token: &str) -> Result<Self, ApiError> {
+        let claims = decode_token(config, token)?;
+        if !db.has_session(&claims).await? {
+            return Err(ApiError::Unauthenticated("Session expired".to_string()));
+        }
+        let account = db.get_account_by_id(claims.sub, &Projection::all()).await?;
+        let access = db.access_by_user_id(claims.sub).await?;
+        Ok(Self {
+            account,
+            access,
+            claims,
+        })
+    }
+
+    /// The API is not bound to a realm, so the highest level over all realms is used,
+    /// rows with realmid -1 apply to every realm anyway.
+    pub fn role(&self) -> Role {
+        role_of(&self.access)
+    }
+
+    pub fn is_at_least(&self, role: Role) -> bool {
+        self.role() >= role
+    }
+
+    /// Staff can only act on accounts with a lower role, like `HasLowerSecurityAccount` in the worldserver
+    pub fn outranks(&self, access: &[Access]) -> bool {
+        self.role() > role_of(access)
+    }
+
+    /// Players can only see their own account, game masters and above can see any
+    pub fn can_read_account(&self, id: ID) -> bool {
+        self.account.id == id || self.is_at_least(Role::GameMaster)
+    }
+
+    pub fn can_see(&self, config: &Config, id: ID, visibility: Visibility) -> bool {
+        match visibility {
+            Visibility::Private => self.can_read_account(id),
+            Visibility::Secret => {
+                config.expose_account_secrets() && self.is_at_least(Role::Administrator)
+            }
+        }
+    }
+}
+
+/// Highest role of the access rows of an account
+pub fn role_of(access: &[Access]) -> Role {
+    access
+        .iter()
+        .map(|a| a.gmlevel)
+        .max()
+        .map_or(Role::Player, Role::from)
+}
+
+/// Who may read a restricted `Account` field
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    /// Personal data like emails and ips, visible to the owner and game masters
+    Private,
+    /// SRP6 and TOTP material, only visible to administrators when `EXPOSE_ACCOUNT_SECRETS` is set
+    Secret,
+}
+
+/// Address of the client that sent the request
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+pub fn client_ip(ctx: &Context<'_>) -> Result<IpAddr, ApiError> {
+    ctx.data_opt::<ClientIp>()
+        .map(|ip| ip.0)
+        .ok_or_else(|| ApiError::Internal("Client address is unknown".to_string()))
+}
+
+/// Returns the caller of the request or an error for anonymous requests
+pub fn caller<'a>(ctx: &Context<'a>) -> Result<&'a Caller, ApiError> {
+    ctx.data_opt::<Caller>()
+        .ok_or_else(|| ApiError::Unauthenticated("Not logged in".to_string()))
+}
+
+pub struct RoleGuard {
+    role: Role,
+}
+
+impl RoleGuard {
+    pub fn new(role: Role) -> Self {
+        Self { role }
+    }
+}
+
+#[async_trait::async_trait]
+impl Guard for RoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        if caller(ctx)?.is_at_least(self.role) {
+            Ok(())
+        } else {
+            Err(ApiError::forbidden().into())
+        }
+    }
+}
+
+/// Guards a field of the account with the given id
+pub struct VisibilityGuard {
+    id: ID,
+    visibility: Visibility,
+}
+
+impl VisibilityGuard {
+    pub fn new(id: ID, visibility: Visibility) -> Self {
+        Self { id, visibility }
+    }
+}
+
+#[async_trait::async_trait]
+impl Guard for VisibilityGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let config = ctx.data_unchecked::<Config>();
+        if caller(ctx)?.can_see(config, self.id, self.visibility) {
+            Ok(())
+        } else {
+            Err(ApiError::forbidden().into())
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::net::IpAddr;
+
+use log::{error, info};
+
+use crate::config::Config;
+
+struct IpLocationRecord {
+    ip_from: u32,
+    ip_to: u32,
+    country_code: String,
+}
+
+/// Country codes of ipv4 ranges from the IP2Location CSV authserver reads as `IPLocationFile`.
+/// Codes are lowercased like `IpLocationStore::Load` does, that is how `account.lock_country` stores them.
+pub struct IpLocation {
+    records: Vec<IpLocationRecord>,
+}
+
+impl IpLocation {
+    pub fn new(config: &Config) -> Self {
+        let records = config
+            .ip_location_file()
+            .map_or_else(Vec::new, |path| Self::read_records(&path));
+        Self { records }
+    }
+
+    /// Lines look like `"16777216","16777471","US","United States of America"`
+    fn read_records(path: &str) -> Vec<IpLocationRecord> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                error!("IP location file {} cannot be read: {}", path, e);
+                return Vec::new();
+            }
+        };
+        let records = Self::parse_records(&content);
+        info!("Loaded {} ip location ranges", records.len());
+        records
+    }
+
+    fn parse_records(content: &str) -> Vec<IpLocationRecord> {
+        let mut records = content
+            .lines()
+            .filter_map(|line| {
+                let mut columns = line.split(',').map(|c| c.trim().trim_matches('"'));
+                let ip_from = columns.next()?.parse().ok()?;
+                let ip_to = columns.next()?.parse().ok()?;
+                let country_code = columns.next()?.to_ascii_lowercase();
+                Some(IpLocationRecord {
+                    ip_from,
+                    ip_to,
+                    country_code,
+                })
+            })
+            .collect::<Vec<_>>();
+        records.sort_by_key(|r| r.ip_from);
+        records
+    }
+
+    /// Country code of an address like `IpLocationStore::GetLocationRecord`, only ipv4 is covered.
+    /// Unlike there the last address of a range is found as well, `ip_to` is inclusive in the CSV.
+    pub fn country_code(&self, ip: IpAddr) -> Option<&str> {
+        let ip = match ip {
+            IpAddr::V4(ip) => u32::from(ip),
+            IpAddr::V6(ip) => u32::from(ip.to_ipv4_mapped()?),
+        };
+        // the first range not ending before the address, `ip_to` is part of the range
+        let index = self.records.partition_point(|r| r.ip_to < ip);
+        self.records
+            .get(index)
+            .filter(|r| r.ip_from <= ip)
+            .map(|r| r.country_code.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "\"16777216\",\"16777471\",\"US\",\"United States of America\"
+\"16777472\",\"16778239\",\"CN\",\"China\"
+\"16778496\",\"16779007\",\"AU\",\"Australia\"
+not,a,record
+";
+
+    fn location() -> IpLocation {
+        IpLocation {
+            records: IpLocation::parse_records(CSV),
+        }
+    }
+
+    fn country(ip: &str) -> Option<String> {
+        location()
+            .country_code(ip.parse().unwrap())
+            .map(str::to_string)
+    }
+
+    #[test]
+    fn ranges_include_their_first_and_last_address() {
+        assert_eq!(country("1.0.0.0").as_deref(), Some("us"));
+        assert_eq!(country("1.0.0.255").as_deref(), Some("us"));
+        assert_eq!(country("1.0.1.0").as_deref(), Some("cn"));
+        assert_eq!(country("1.0.3.255").as_deref(), Some("cn"));
+        assert_eq!(country("1.0.5.0").as_deref(), Some("au"));
+        assert_eq!(country("1.0.6.255").as_deref(), Some("au"));
+    }
+
+    #[test]
+    fn addresses_outside_every_range_are_unknown() {
+        assert_eq!(country("0.255.255.255"), None);
+        assert_eq!(country("1.0.4.0"), None);
+        assert_eq!(country("1.0.7.0"), None);
+        assert_eq!(country("255.255.255.255"), None);
+        assert_eq!(country("2001:db8::1"), None);
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_are_looked_up_as_ipv4() {
+        assert_eq!(country("::ffff:1.0.0.1").as_deref(), Some("us"));
+    }
+
+    #[test]
+    fn records_without_numeric_ranges_are_skipped() {
+        assert_eq!(location().records.len(), 3);
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use async_graphql::{async_trait, dataloader::Loader};
+
+use super::account::{access::Access, realmcharacters::RealmCharacter, ID};
+use super::db::Storage;
+use super::error::ApiError;
+
+/// Batches `Account.access` of all accounts in a response into one query
+pub struct AccessLoader {
+    db: Storage,
+}
+
+impl AccessLoader {
+    pub fn new(db: Storage) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait::async_trait]
+impl Loader<ID> for AccessLoader {
+    type Value = Vec<Access>;
+    type Error = ApiError;
+
+    async fn load(&self, keys: &[ID]) -> Result<HashMap<ID, Self::Value>, Self::Error> {
+        let mut access = HashMap::<ID, Vec<Access>>::new();
+        for row in self.db.access_by_user_ids(keys).await? {
+            access.entry(row.id).or_default().push(row);
+        }
+        Ok(access)
+    }
+}
+
+/// Batches `Account.realmcharacters` of all accounts in a response into one query
+pub struct RealmCharactersLoader {
+    db: Storage,
+}
+
+impl RealmCharactersLoader {
+    pub fn new(db: Storage) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait::async_trait]
+impl Loader<ID> for RealmCharactersLoader {
+    type Value = Vec<RealmCharacter>;
+    type Error = ApiError;
+
+    async fn load(&self, keys: &[ID]) -> Result<HashMap<ID, Self::Value>, Self::Error> {
+        let mut realmcharacters = HashMap::<ID, Vec<RealmCharacter>>::new();
+        for row in self.db.realmcharacters_by_user_ids(keys).await? {
+            realmcharacters.entry(row.acctid).or_default().push(row);
+        }
+        Ok(realmcharacters)
+    }
+}
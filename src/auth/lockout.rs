@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::config::Config;
+
+use super::account::{projection::Projection, ID};
+use super::db::{AuthDB, BANNED_BY};
+use super::error::ApiError;
+use super::iplocation::IpLocation;
+
+/// `account_banned.banreason` and `ip_banned.banreason` of bans placed after too many wrong passwords
+pub const FAILED_LOGIN_BAN_REASON: &str = "Failed login autoban";
+
+/// `WrongPass.BanType` of authserver.conf
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BanType {
+    Ip = 0,
+    Account = 1,
+}
+
+impl From<u8> for BanType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => BanType::Account,
+            _ => BanType::Ip,
+        }
+    }
+}
+
+/// Applies the ip and country locks of an account like authserver does at logon,
+/// addresses without a known country pass the country lock there as well
+pub async fn check_account_lock(
+    db: &AuthDB,
+    ip_location: &IpLocation,
+    account_id: ID,
+    ip: IpAddr,
+) -> Result<(), ApiError> {
+    let account = db
+        .get_account_by_id(
+            account_id,
+            &Projection::of(&["locked", "last_ip", "lock_country"]),
+        )
+        .await?;
+    if account.locked == 1 && account.last_ip != ip.to_string() {
+        return Err(ApiError::Forbidden(
+            "Account is locked to another ip".to_string(),
+        ));
+    }
+    if !account.lock_country.is_empty() && account.lock_country != "00" {
+        let country = ip_location.country_code(ip);
+        if country.is_some_and(|c| c != account.lock_country) {
+            return Err(ApiError::Forbidden(
+                "Account is locked to another country".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Counts a wrong password against an account like authserver does:
+/// once `failed_logins` reaches `WRONG_PASS_MAX_COUNT` the account or the ip is banned
+/// for `WRONG_PASS_BAN_TIME` seconds and the counter starts over.
+pub async fn record_failed_login(
+    db: &AuthDB,
+    config: &Config,
+    account_id: ID,
+    ip: IpAddr,
+) -> Result<(), ApiError> {
+    let ip = ip.to_string();
+    let failed_logins = db.record_failed_login(account_id, &ip).await?;
+    let max_count = config.wrong_pass_max_count();
+    if max_count == 0 || failed_logins < max_count {
+        return Ok(());
+    }
+    let duration = config.wrong_pass_ban_time();
+    // ip_banned.ip only fits IPv4 addresses
+    if config.wrong_pass_ban_type() == BanType::Ip && ip.len() <= 15 {
+        info!("Banning ip {} after {} failed logins", ip, failed_logins);
+        db.ban_ip(&ip, duration, BANNED_BY, FAILED_LOGIN_BAN_REASON)
+            .await?;
+    } else {
+        info!(
+            "Banning account {} after {} failed logins",
+            account_id, failed_logins
+        );
+        db.ban_account(account_id, duration, BANNED_BY, FAILED_LOGIN_BAN_REASON)
+            .await?;
+    }
+    db.reset_failed_logins(account_id, &ip).await
+}
+
+/// In memory counter of attempts per ip, like failed logins and `checkUsername`.
+/// Unlike `WRONG_PASS_MAX_COUNT` it applies without any configuration.
+pub struct AttemptLimiter {
+    attempts: Mutex<HashMap<IpAddr, (u32, Instant)>>,
+    max_count: u32,
+    window: Duration,
+}
+
+impl AttemptLimiter {
+    pub fn new(max_count: u32, window: Duration) -> Self {
+        Self {
+            attempts: Mutex::new(HashMap::new()),
+            max_count,
+            window,
+        }
+    }
+
+    /// Counts an attempt, returns false once the ip used up its attempts in the current window
+    pub fn hit(&self, ip: IpAddr) -> bool {
+        if self.max_count == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().unwrap();
+        attempts.retain(|_, (_, start)| now.duration_since(*start) < self.window);
+        let (count, _) = attempts.entry(ip).or_insert((0, now));
+        *count += 1;
+        *count <= self.max_count
+    }
+
+    /// Counts a failed attempt, `error` is replaced by a rate limit error once the ip is limited
+    pub fn fail(&self, ip: IpAddr, error: ApiError) -> ApiError {
+        if self.hit(ip) {
+            error
+        } else {
+            ApiError::rate_limited()
+        }
+    }
+
+    pub fn is_limited(&self, ip: IpAddr) -> bool {
+        if self.max_count == 0 {
+            return false;
+        }
+        let attempts = self.attempts.lock().unwrap();
+        matches!(attempts.get(&ip), Some((count, start))
+            if *count >= self.max_count && start.elapsed() < self.window)
+    }
+}
@@ -0,0 +1,142 @@
+use std::net::IpAddr;
+
+use async_graphql::{InputValueError, InputValueResult, Scalar, ScalarType, Value};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sqlx::types::chrono::{DateTime, TimeZone, Utc};
+
+use super::validation::Validator;
+
+/// An RFC 3339 timestamp in UTC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timestamp(pub DateTime<Utc>);
+
+impl Timestamp {
+    /// The auth tables store ban and mute dates as unix timestamps
+    pub fn from_unix(seconds: i64) -> Self {
+        Self(Utc.timestamp_opt(seconds, 0).unwrap())
+    }
+}
+
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(datetime: DateTime<Utc>) -> Self {
+        Self(datetime)
+    }
+}
+
+#[Scalar(name = "DateTime")]
+impl ScalarType for Timestamp {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::String(s) => DateTime::parse_from_rfc3339(s)
+                .map(|d| Self(d.with_timezone(&Utc)))
+                .map_err(|_| InputValueError::custom("Not an RFC 3339 timestamp")),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.0.to_rfc3339())
+    }
+}
+
+/// An email address as `createAccount` accepts it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Email(pub String);
+
+impl Email {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[Scalar]
+impl ScalarType for Email {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::String(s) => Validator::email("email", s)
+                .map(|_| Self(s.clone()))
+                .map_err(|e| InputValueError::custom(e.message())),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.0.clone())
+    }
+}
+
+/// The name of an existing account, new accounts are held to the stricter rules of `createAccount`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Username(pub String);
+
+impl Username {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[Scalar]
+impl ScalarType for Username {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::String(s) => Validator::account_name("username", s)
+                .map(|_| Self(s.clone()))
+                .map_err(|e| InputValueError::custom(e.message())),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.0.clone())
+    }
+}
+
+/// An ipv4 or ipv6 address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpAddress(pub IpAddr);
+
+impl IpAddress {
+    /// Addresses are stored as strings, which are empty for accounts that never logged in
+    pub fn from_stored(ip: &str) -> Option<Self> {
+        ip.parse().ok().map(Self)
+    }
+}
+
+#[Scalar]
+impl ScalarType for IpAddress {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::String(s) => s
+                .parse()
+                .map(Self)
+                .map_err(|_| InputValueError::custom("Not a valid ip address")),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.0.to_string())
+    }
+}
+
+/// Binary data, returned as lowercase hex. Input can be hex or padded base64,
+/// strings that are valid hex are always read as hex.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bytes(pub Vec<u8>);
+
+#[Scalar]
+impl ScalarType for Bytes {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::String(s) => hex::decode(s)
+                .or_else(|_| STANDARD.decode(s))
+                .map(Self)
+                .map_err(|_| InputValueError::custom("Not valid hex or base64")),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(hex::encode(&self.0))
+    }
+}
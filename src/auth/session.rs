@@ -0,0 +1,79 @@
+use async_graphql::Object;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, TimeZone, Utc};
+
+use crate::config::Config;
+
+use super::account::ID;
+use super::error::ApiError;
+use super::scalar::Timestamp;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Claims {
+    /// account id
+    pub sub: ID,
+    /// session id, the key of the `api_session` row
+    pub jti: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+impl Claims {
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc.timestamp_opt(self.exp, 0).unwrap()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    token: String,
+    claims: Claims,
+}
+
+impl Session {
+    pub fn issue(config: &Config, account_id: ID) -> Result<Self, ApiError> {
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            sub: account_id,
+            jti: hex::encode(rand::random::<[u8; 16]>()),
+            iat: now,
+            exp: now + config.session_ttl(),
+        };
+        let token = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(config.session_secret()),
+        )
+        .map_err(|e| ApiError::Internal(format!("Session cannot be created: {}", e)))?;
+        Ok(Self { token, claims })
+    }
+    pub fn claims(&self) -> &Claims {
+        &self.claims
+    }
+}
+
+/// Checks signature and expiration of a token, whether the session was revoked
+/// is up to the caller to check against the `api_session` table.
+pub fn decode_token(config: &Config, token: &str) -> Result<Claims, ApiError> {
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(config.session_secret()),
+        &Validation::default(),
+    )
+    .map(|data| data.claims)
+    .map_err(|_| ApiError::Unauthenticated("Invalid session token".to_string()))
+}
+
+#[Object]
+impl Session {
+    async fn token(&self) -> String {
+        self.token.clone()
+    }
+    async fn account_id(&self) -> ID {
+        self.claims.sub
+    }
+    async fn expires_at(&self) -> Timestamp {
+        Timestamp(self.claims.expires_at())
+    }
+}
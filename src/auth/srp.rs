@@ -0,0 +1,105 @@
+use wow_srp::client::SrpClientUser;
+use wow_srp::normalized_string::NormalizedString;
+use wow_srp::server::SrpVerifier;
+use wow_srp::{PublicKey, GENERATOR, LARGE_SAFE_PRIME_LITTLE_ENDIAN};
+
+use super::error::ApiError;
+
+/// Generates a new salt and the verifier for it, returns them together with the normalized username
+pub fn make_verifier(
+    username: &str,
+    password: &str,
+) -> Result<(Vec<u8>, Vec<u8>, String), ApiError> {
+    let username = NormalizedString::new(username).map_err(|_| {
+        ApiError::invalid(
+            "username",
+            "INVALID_CHARACTERS",
+            "Username is too long or has invalid characters",
+        )
+    })?;
+    let password = NormalizedString::new(password).map_err(|_| {
+        ApiError::invalid(
+            "password",
+            "INVALID_CHARACTERS",
+            "Password is too long or has invalid characters",
+        )
+    })?;
+    let verifier = SrpVerifier::from_username_and_password(username, password);
+    Ok((
+        verifier.salt().to_vec(),
+        verifier.password_verifier().to_vec(),
+        verifier.username().to_string(),
+    ))
+}
+
+/// Checks a password against the `salt` and `verifier` stored in the `account` table.
+///
+/// The verifier cannot be recomputed for a given salt through the `wow_srp` API,
+/// so this plays both sides of the SRP6 handshake the game client does against authserver.
+/// Stored values that are not 32 bytes long fail instead of counting as a wrong password.
+pub fn verify_password(
+    username: &str,
+    password: &str,
+    salt: &[u8],
+    verifier: &[u8],
+) -> Result<bool, ApiError> {
+    let (username, password) = match (
+        NormalizedString::new(username),
+        NormalizedString::new(password),
+    ) {
+        (Ok(u), Ok(p)) => (u, p),
+        _ => return Ok(false),
+    };
+    let malformed = || ApiError::Internal("The stored salt or verifier is malformed".to_string());
+    let salt: [u8; 32] = salt.try_into().map_err(|_| malformed())?;
+    let verifier: [u8; 32] = verifier.try_into().map_err(|_| malformed())?;
+
+    let proof = SrpVerifier::from_database_values(username.clone(), verifier, salt).into_proof();
+    let server_public_key =
+        PublicKey::from_le_bytes(proof.server_public_key()).map_err(|_| malformed())?;
+    let challenge = SrpClientUser::new(username, password).into_challenge(
+        GENERATOR,
+        LARGE_SAFE_PRIME_LITTLE_ENDIAN,
+        server_public_key,
+        salt,
+    );
+    let client_public_key = match PublicKey::from_le_bytes(challenge.client_public_key()) {
+        Ok(key) => key,
+        Err(_) => return Ok(false),
+    };
+
+    Ok(proof
+        .into_server(client_public_key, *challenge.client_proof())
+        .is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifier_accepts_only_its_password() {
+        let (salt, verifier, username) = make_verifier("player", "secret1").unwrap();
+        assert_eq!(username, "PLAYER");
+        assert!(verify_password("player", "secret1", &salt, &verifier).unwrap());
+        assert!(verify_password("PLAYER", "SECRET1", &salt, &verifier).unwrap());
+        assert!(!verify_password("player", "secret2", &salt, &verifier).unwrap());
+        assert!(!verify_password("other", "secret1", &salt, &verifier).unwrap());
+    }
+
+    #[test]
+    fn malformed_stored_values_are_errors() {
+        let (salt, verifier, _) = make_verifier("player", "secret1").unwrap();
+        assert!(verify_password("player", "secret1", &salt[..31], &verifier).is_err());
+        assert!(verify_password("player", "secret1", &salt, &[]).is_err());
+        assert!(verify_password("player", "secret1", &[], &[]).is_err());
+    }
+
+    #[test]
+    fn invalid_input_is_rejected() {
+        assert!(make_verifier(&"a".repeat(17), "secret1").is_err());
+        assert!(make_verifier("player", "pässword").is_err());
+        let (salt, verifier, _) = make_verifier("player", "secret1").unwrap();
+        assert!(!verify_password("player", "pässword", &salt, &verifier).unwrap());
+    }
+}
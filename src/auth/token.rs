@@ -0,0 +1,11 @@
+use sha2::{Digest, Sha256};
+
+/// Random token for links sent by mail
+pub fn generate() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+/// Tokens are stored hashed, so a leaked table does not allow to use them
+pub fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,206 @@
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aes::cipher::consts::U12;
+use aes_gcm::aes::Aes128;
+use aes_gcm::{AeadInPlace, AesGcm, KeyInit};
+use async_graphql::Object;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sqlx::types::chrono::Utc;
+
+use super::error::ApiError;
+
+/// `Acore::Crypto::AES`, AES-128-GCM with a 12 byte IV and a 12 byte tag
+type Aes = AesGcm<Aes128, U12, U12>;
+
+const IV_SIZE: usize = 12;
+const TAG_SIZE: usize = 12;
+/// `Acore::Crypto::TOTP::RECOMMENDED_SECRET_LENGTH`
+const SECRET_LENGTH: usize = 20;
+const INTERVAL: i64 = 30;
+
+pub fn generate_secret() -> Vec<u8> {
+    rand::random::<[u8; SECRET_LENGTH]>().to_vec()
+}
+
+/// The secret as authenticator apps expect it, unpadded RFC 4648 base32
+pub fn encode_secret(secret: &[u8]) -> String {
+    base32::encode(base32::Alphabet::RFC4648 { padding: false }, secret)
+}
+
+pub fn otpauth_uri(issuer: &str, username: &str, secret: &[u8]) -> String {
+    let issuer = url_encode(issuer);
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}",
+        issuer,
+        url_encode(username),
+        encode_secret(secret),
+        issuer
+    )
+}
+
+fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn generate_token(secret: &[u8], counter: u64) -> u32 {
+    let mut mac =
+        <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0xF) as usize;
+    let code = u32::from_be_bytes([
+        hash[offset] & 0x7F,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    code % 1_000_000
+}
+
+/// Same rules as `Acore::Crypto::TOTP::ValidateToken`, the previous and the next interval are accepted too
+pub fn validate_token(secret: &[u8], token: &str) -> bool {
+    let token = match token.trim().parse::<u32>() {
+        Ok(token) => token,
+        Err(_) => return false,
+    };
+    let counter = Utc::now().timestamp() / INTERVAL;
+    (counter - 1..=counter + 1).any(|c| generate_token(secret, c as u64) == token)
+}
+
+/// Encrypts a secret for `account.totp_secret` like `AEEncryptWithRandomIV<AES>` does:
+/// the ciphertext is followed by the IV and the tag.
+/// Without a master key the secret is stored as is, as the worldserver does.
+pub fn encrypt_secret(master_key: Option<&[u8; 16]>, secret: &[u8]) -> Result<Vec<u8>, ApiError> {
+    let key = match master_key {
+        Some(key) => key,
+        None => return Ok(secret.to_vec()),
+    };
+    let iv = rand::random::<[u8; IV_SIZE]>();
+    let mut data = secret.to_vec();
+    let tag = Aes::new(GenericArray::from_slice(key))
+        .encrypt_in_place_detached(GenericArray::from_slice(&iv), &[], &mut data)
+        .map_err(|_| ApiError::Internal("TOTP secret cannot be encrypted".to_string()))?;
+    data.extend_from_slice(&iv);
+    data.extend_from_slice(&tag);
+    Ok(data)
+}
+
+pub fn decrypt_secret(master_key: Option<&[u8; 16]>, data: &[u8]) -> Result<Vec<u8>, ApiError> {
+    let key = match master_key {
+        Some(key) => key,
+        None => return Ok(data.to_vec()),
+    };
+    if data.len() < IV_SIZE + TAG_SIZE {
+        return Err(ApiError::Internal(
+            "TOTP secret cannot be decrypted".to_string(),
+        ));
+    }
+    let (secret, trailer) = data.split_at(data.len() - IV_SIZE - TAG_SIZE);
+    let (iv, tag) = trailer.split_at(IV_SIZE);
+    let mut secret = secret.to_vec();
+    Aes::new(GenericArray::from_slice(key))
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(iv),
+            &[],
+            &mut secret,
+            GenericArray::from_slice(tag),
+        )
+        .map_err(|_| ApiError::Internal("TOTP secret cannot be decrypted".to_string()))?;
+    Ok(secret)
+}
+
+pub struct TotpEnrollment {
+    secret: Vec<u8>,
+    uri: String,
+}
+
+impl TotpEnrollment {
+    pub fn new(issuer: &str, username: &str, secret: Vec<u8>) -> Self {
+        let uri = otpauth_uri(issuer, username, &secret);
+        Self { secret, uri }
+    }
+}
+
+#[Object]
+impl TotpEnrollment {
+    /// Base32 secret for manual entry into an authenticator app
+    async fn secret(&self) -> String {
+        encode_secret(&self.secret)
+    }
+    /// `otpauth://` URI to render as a QR code
+    async fn uri(&self) -> String {
+        self.uri.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seed of the SHA1 test vectors in RFC 6238 appendix B
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+    const MASTER_KEY: [u8; 16] = *b"0123456789abcdef";
+
+    #[test]
+    fn generate_token_matches_rfc_6238() {
+        // the RFC lists 8 digits, authenticators and the worldserver use the last 6
+        for (time, token) in [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ] {
+            assert_eq!(generate_token(RFC_SECRET, time / INTERVAL as u64), token);
+        }
+    }
+
+    #[test]
+    fn validate_token_accepts_the_current_interval() {
+        let counter = (Utc::now().timestamp() / INTERVAL) as u64;
+        let token = format!("{:06}", generate_token(RFC_SECRET, counter));
+        assert!(validate_token(RFC_SECRET, &token));
+        assert!(validate_token(RFC_SECRET, &format!(" {} ", token)));
+        assert!(!validate_token(RFC_SECRET, "abcdef"));
+        assert!(!validate_token(
+            RFC_SECRET,
+            &format!("{:06}", generate_token(RFC_SECRET, counter + 5))
+        ));
+    }
+
+    #[test]
+    fn encode_secret_is_unpadded_base32() {
+        assert_eq!(encode_secret(b"foobar"), "MZXW6YTBOI");
+    }
+
+    #[test]
+    fn encrypted_secret_decrypts_with_the_master_key() {
+        let secret = generate_secret();
+        let data = encrypt_secret(Some(&MASTER_KEY), &secret).unwrap();
+        assert_eq!(data.len(), SECRET_LENGTH + IV_SIZE + TAG_SIZE);
+        assert_ne!(&data[..SECRET_LENGTH], secret.as_slice());
+        assert_eq!(decrypt_secret(Some(&MASTER_KEY), &data).unwrap(), secret);
+    }
+
+    #[test]
+    fn decrypt_secret_rejects_a_wrong_key_and_short_data() {
+        let data = encrypt_secret(Some(&MASTER_KEY), &generate_secret()).unwrap();
+        assert!(decrypt_secret(Some(b"fedcba9876543210"), &data).is_err());
+        assert!(decrypt_secret(Some(&MASTER_KEY), &data[..IV_SIZE + TAG_SIZE - 1]).is_err());
+    }
+
+    #[test]
+    fn secrets_are_stored_as_is_without_a_master_key() {
+        let secret = generate_secret();
+        assert_eq!(encrypt_secret(None, &secret).unwrap(), secret);
+        assert_eq!(decrypt_secret(None, &secret).unwrap(), secret);
+    }
+}
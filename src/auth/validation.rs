@@ -0,0 +1,430 @@
+use std::collections::HashSet;
+
+use async_graphql::{Error, ErrorExtensions, Value};
+use log::{error, info};
+use wow_srp::normalized_string::MAXIMUM_STRING_LENGTH_IN_BYTES;
+
+use crate::config::Config;
+
+/// The game client does not accept longer account names, although `MAX_ACCOUNT_STR` is 20
+pub const USERNAME_MAX_LENGTH: usize = MAXIMUM_STRING_LENGTH_IN_BYTES as usize;
+/// `MAX_ACCOUNT_STR`, accounts created in game or by other tools can have names up to this length
+pub const ACCOUNT_NAME_MAX_LENGTH: usize = 20;
+/// `MAX_PASS_STR`, the longest password the WotLK client sends
+pub const PASSWORD_MAX_LENGTH: usize = MAXIMUM_STRING_LENGTH_IN_BYTES as usize;
+/// Size of the `account.email` and `account.reg_mail` columns
+pub const EMAIL_MAX_LENGTH: usize = 255;
+
+#[derive(Clone, Debug)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// All problems found with the input of a request, reported at once
+#[derive(Clone, Debug, Default)]
+pub struct ValidationErrors(Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn single(field: &'static str, code: &'static str, message: impl Into<String>) -> Self {
+        let mut errors = Self::default();
+        errors.add(field, code, message);
+        errors
+    }
+    fn add(&mut self, field: &'static str, code: &'static str, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field,
+            code,
+            message: message.into(),
+        });
+    }
+    /// All messages in one line, for errors that cannot list the fields
+    pub fn message(&self) -> String {
+        self.0
+            .iter()
+            .map(|e| e.message.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+    fn into_result(self) -> Result<(), Self> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl From<ValidationErrors> for Error {
+    fn from(errors: ValidationErrors) -> Self {
+        let fields = errors
+            .0
+            .iter()
+            .map(|e| {
+                let mut field = async_graphql::indexmap::IndexMap::new();
+                field.insert(async_graphql::Name::new("field"), Value::from(e.field));
+                field.insert(async_graphql::Name::new("code"), Value::from(e.code));
+                field.insert(
+                    async_graphql::Name::new("message"),
+                    Value::from(e.message.as_str()),
+                );
+                Value::Object(field)
+            })
+            .collect::<Vec<_>>();
+        Error::new("Invalid input").extend_with(|_, ext| ext.set("fields", Value::List(fields)))
+    }
+}
+
+/// Username, password and email rules for new accounts and password changes
+pub struct Validator {
+    password_min_length: usize,
+    password_require_letter: bool,
+    password_require_digit: bool,
+    password_require_symbol: bool,
+    banned_passwords: HashSet<String>,
+}
+
+impl Validator {
+    pub fn new(config: &Config) -> Self {
+        let banned_passwords = config
+            .password_banned_list()
+            .map_or_else(HashSet::new, |path| Self::read_banned_passwords(&path));
+        Self {
+            password_min_length: config.password_min_length(),
+            password_require_letter: config.password_require_letter(),
+            password_require_digit: config.password_require_digit(),
+            password_require_symbol: config.password_require_symbol(),
+            banned_passwords,
+        }
+    }
+
+    /// One password per line, passwords are case insensitive for the game so they are compared uppercased
+    fn read_banned_passwords(path: &str) -> HashSet<String> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => {
+                let passwords = content
+                    .lines()
+                    .map(|l| l.trim().to_ascii_uppercase())
+                    .filter(|l| !l.is_empty())
+                    .collect::<HashSet<_>>();
+                info!("Loaded {} banned passwords", passwords.len());
+                passwords
+            }
+            Err(e) => {
+                error!("Banned password list {} cannot be read: {}", path, e);
+                HashSet::new()
+            }
+        }
+    }
+
+    pub fn new_account(
+        &self,
+        username: &str,
+        password: &str,
+        email: &str,
+    ) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        Self::check_username(&mut errors, "username", username);
+        self.check_password(&mut errors, "password", password, Some(username));
+        Self::check_email(&mut errors, "email", email);
+        errors.into_result()
+    }
+
+    /// Checks a new password, `username` is given when the account is known
+    pub fn password(
+        &self,
+        field: &'static str,
+        password: &str,
+        username: Option<&str>,
+    ) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        self.check_password(&mut errors, field, password, username);
+        errors.into_result()
+    }
+
+    /// Rules of the `Username` scalar that looks up existing accounts, only the length is checked
+    /// as older accounts may not follow the rules of new ones
+    pub fn account_name(field: &'static str, username: &str) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if username.is_empty() {
+            errors.add(field, "REQUIRED", "Username is required");
+        } else if username.chars().count() > ACCOUNT_NAME_MAX_LENGTH {
+            errors.add(
+                field,
+                "TOO_LONG",
+                format!(
+                    "Username must be at most {} characters",
+                    ACCOUNT_NAME_MAX_LENGTH
+                ),
+            );
+        }
+        errors.into_result()
+    }
+
+    /// Rules of the `Email` scalar
+    pub fn email(field: &'static str, email: &str) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        Self::check_email(&mut errors, field, email);
+        errors.into_result()
+    }
+
+    fn check_username(errors: &mut ValidationErrors, field: &'static str, username: &str) {
+        if username.is_empty() {
+            errors.add(field, "REQUIRED", "Username is required");
+        } else if username.len() > USERNAME_MAX_LENGTH {
+            errors.add(
+                field,
+                "TOO_LONG",
+                format!(
+                    "Username must be at most {} characters",
+                    USERNAME_MAX_LENGTH
+                ),
+            );
+        }
+        if !username.chars().all(|c| c.is_ascii_alphanumeric()) {
+            errors.add(
+                field,
+                "INVALID_CHARACTERS",
+                "Username can only contain latin letters and digits",
+            );
+        }
+    }
+
+    fn check_password(
+        &self,
+        errors: &mut ValidationErrors,
+        field: &'static str,
+        password: &str,
+        username: Option<&str>,
+    ) {
+        if password.len() < self.password_min_length.max(1) {
+            errors.add(
+                field,
+                "TOO_SHORT",
+                format!(
+                    "Password must be at least {} characters",
+                    self.password_min_length.max(1)
+                ),
+            );
+        } else if password.len() > PASSWORD_MAX_LENGTH {
+            errors.add(
+                field,
+                "TOO_LONG",
+                format!(
+                    "Password must be at most {} characters",
+                    PASSWORD_MAX_LENGTH
+                ),
+            );
+        }
+        if !password
+            .chars()
+            .all(|c| c.is_ascii() && !c.is_ascii_control())
+        {
+            errors.add(
+                field,
+                "INVALID_CHARACTERS",
+                "Password can only contain printable ASCII characters",
+            );
+        }
+        if self.password_require_letter && !password.chars().any(|c| c.is_ascii_alphabetic()) {
+            errors.add(field, "MISSING_LETTER", "Password must contain a letter");
+        }
+        if self.password_require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            errors.add(field, "MISSING_DIGIT", "Password must contain a digit");
+        }
+        if self.password_require_symbol && !password.chars().any(|c| c.is_ascii_punctuation()) {
+            errors.add(field, "MISSING_SYMBOL", "Password must contain a symbol");
+        }
+        let normalized = password.to_ascii_uppercase();
+        if username.is_some_and(|u| u.eq_ignore_ascii_case(password)) {
+            errors.add(
+                field,
+                "SAME_AS_USERNAME",
+                "Password cannot be the same as the username",
+            );
+        }
+        if self.banned_passwords.contains(&normalized) {
+            errors.add(field, "BANNED", "Password is too common");
+        }
+    }
+
+    fn check_email(errors: &mut ValidationErrors, field: &'static str, email: &str) {
+        if email.len() > EMAIL_MAX_LENGTH {
+            errors.add(
+                field,
+                "TOO_LONG",
+                format!("Email must be at most {} characters", EMAIL_MAX_LENGTH),
+            );
+        }
+        if !email
+            .chars()
+            .all(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '@' | '.' | '+' | '-' | '_'))
+        {
+            errors.add(field, "INVALID_CHARACTERS", "Email has invalid characters");
+        }
+        match email.split_once('@') {
+            Some((local, domain))
+                if !local.is_empty() && domain.contains('.') && !domain.contains('@') => {}
+            _ => errors.add(field, "INVALID_FORMAT", "Email is not valid"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validator() -> Validator {
+        Validator {
+            password_min_length: 6,
+            password_require_letter: true,
+            password_require_digit: true,
+            password_require_symbol: false,
+            banned_passwords: ["PASSWORD1".to_string()].into_iter().collect(),
+        }
+    }
+
+    fn codes(result: Result<(), ValidationErrors>) -> Vec<(&'static str, &'static str)> {
+        result
+            .err()
+            .map(|errors| errors.0.iter().map(|e| (e.field, e.code)).collect())
+            .unwrap_or_default()
+    }
+
+    fn username_codes(username: &str) -> Vec<(&'static str, &'static str)> {
+        codes(validator().new_account(username, "secret1", "player@example.com"))
+    }
+
+    #[test]
+    fn new_usernames_are_latin_letters_and_digits_up_to_the_client_limit() {
+        assert!(username_codes("Player1").is_empty());
+        assert!(username_codes(&"a".repeat(USERNAME_MAX_LENGTH)).is_empty());
+        assert_eq!(username_codes(""), [("username", "REQUIRED")]);
+        assert_eq!(
+            username_codes(&"a".repeat(USERNAME_MAX_LENGTH + 1)),
+            [("username", "TOO_LONG")]
+        );
+        assert_eq!(username_codes("Jörg"), [("username", "INVALID_CHARACTERS")]);
+    }
+
+    #[test]
+    fn existing_account_names_are_only_checked_for_length() {
+        assert!(Validator::account_name("username", "Jörg_Müller").is_ok());
+        assert!(Validator::account_name("username", &"ä".repeat(ACCOUNT_NAME_MAX_LENGTH)).is_ok());
+        assert_eq!(
+            codes(Validator::account_name("username", "")),
+            [("username", "REQUIRED")]
+        );
+        assert_eq!(
+            codes(Validator::account_name(
+                "username",
+                &"a".repeat(ACCOUNT_NAME_MAX_LENGTH + 1)
+            )),
+            [("username", "TOO_LONG")]
+        );
+    }
+
+    #[test]
+    fn passwords_follow_the_configured_rules() {
+        let validator = validator();
+        assert!(validator.password("password", "secret1", None).is_ok());
+        assert!(validator
+            .password(
+                "password",
+                &format!("a1{}", "x".repeat(PASSWORD_MAX_LENGTH - 2)),
+                None
+            )
+            .is_ok());
+        assert_eq!(
+            codes(validator.password("password", "abc1", None)),
+            [("password", "TOO_SHORT")]
+        );
+        assert_eq!(
+            codes(validator.password("password", &"a1".repeat(PASSWORD_MAX_LENGTH), None)),
+            [("password", "TOO_LONG")]
+        );
+        assert_eq!(
+            codes(validator.password("password", "secret\t1", None)),
+            [("password", "INVALID_CHARACTERS")]
+        );
+        assert_eq!(
+            codes(validator.password("password", "123456", None)),
+            [("password", "MISSING_LETTER")]
+        );
+        assert_eq!(
+            codes(validator.password("password", "secret", None)),
+            [("password", "MISSING_DIGIT")]
+        );
+        assert_eq!(
+            codes(validator.password("password", "Password1", None)),
+            [("password", "BANNED")]
+        );
+        assert_eq!(
+            codes(validator.password("password", "PLAYER1", Some("player1"))),
+            [("password", "SAME_AS_USERNAME")]
+        );
+    }
+
+    #[test]
+    fn a_zero_minimum_length_still_requires_a_password() {
+        let validator = Validator {
+            password_min_length: 0,
+            ..validator()
+        };
+        assert_eq!(
+            codes(validator.password("newPassword", "", None)),
+            [
+                ("newPassword", "TOO_SHORT"),
+                ("newPassword", "MISSING_LETTER"),
+                ("newPassword", "MISSING_DIGIT")
+            ]
+        );
+    }
+
+    #[test]
+    fn emails_need_a_local_part_and_a_dotted_domain() {
+        assert!(Validator::email("email", "player+wow@example.com").is_ok());
+        for email in [
+            "",
+            "player",
+            "@example.com",
+            "player@localhost",
+            "a@b@c.com",
+        ] {
+            assert_eq!(
+                codes(Validator::email("email", email)),
+                [("email", "INVALID_FORMAT")],
+                "{}",
+                email
+            );
+        }
+        assert_eq!(
+            codes(Validator::email("email", "pläyer@example.com")),
+            [("email", "INVALID_CHARACTERS")]
+        );
+        assert_eq!(
+            codes(Validator::email(
+                "email",
+                &format!("{}@example.com", "a".repeat(EMAIL_MAX_LENGTH))
+            )),
+            [("email", "TOO_LONG")]
+        );
+    }
+
+    #[test]
+    fn new_accounts_report_every_problem_at_once() {
+        assert_eq!(
+            codes(validator().new_account("", "abc", "player")),
+            [
+                ("username", "REQUIRED"),
+                ("password", "TOO_SHORT"),
+                ("password", "MISSING_DIGIT"),
+                ("email", "INVALID_FORMAT")
+            ]
+        );
+        assert!(validator()
+            .new_account("Player", "secret1", "player@example.com")
+            .is_ok());
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::Duration;
+use sqlx::types::chrono::{DateTime, Utc};
+
+use crate::config::Config;
+use crate::mail::Mail;
+
+use super::account::ID;
+use super::db::AuthDB;
+use super::error::ApiError;
+use super::token;
+
+/// A new verification token, only its hash is stored
+pub struct Verification {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl Verification {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            token: token::generate(),
+            expires_at: Utc::now() + Duration::seconds(config.email_verification_ttl()),
+        }
+    }
+
+    pub fn token_hash(&self) -> String {
+        token::hash(&self.token)
+    }
+
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+
+    /// The mail that sends the token to `reg_mail`
+    pub fn mail(&self, config: &Config, username: &str, reg_mail: &str) -> Mail {
+        let link = config.email_verification_url().map_or_else(
+            || self.token.clone(),
+            |url| url.replace("{token}", &self.token),
+        );
+        Mail {
+            to: reg_mail.to_string(),
+            subject: "Verify your email".to_string(),
+            body: format!(
+                "The account {} was registered with this email.\n\n\
+                Use this to verify it and unlock the account: {}\n\n\
+                It expires at {}, a new one can be requested until the account is deleted.",
+                username,
+                link,
+                self.expires_at.to_rfc3339()
+            ),
+        }
+    }
+}
+
+/// Issues a new verification token for an account and returns the mail to send it with
+pub async fn create_verification_mail(
+    db: &AuthDB,
+    config: &Config,
+    account_id: ID,
+    username: &str,
+    reg_mail: &str,
+) -> Result<Mail, ApiError> {
+    let verification = Verification::new(config);
+    db.create_email_verification(
+        account_id,
+        &verification.token_hash(),
+        verification.expires_at(),
+    )
+    .await?;
+    Ok(verification.mail(config, username, reg_mail))
+}
@@ -1,13 +1,72 @@
+use std::net::IpAddr;
+
+use log::warn;
+
+use crate::auth::account::expansion::Expansion;
+use crate::auth::lockout::BanType;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     host: String,
     port: u16,
     is_secure: bool,
+    session_secret: String,
+    session_ttl: i64,
+    expose_account_secrets: bool,
+    totp_master_key: Option<[u8; 16]>,
+    totp_issuer: String,
+    mail_transport: String,
+    mail_smtp_url: String,
+    mail_file: String,
+    mail_from: String,
+    password_reset_url: Option<String>,
+    password_reset_ttl: i64,
+    require_email_verification: bool,
+    email_verification_url: Option<String>,
+    email_verification_ttl: i64,
+    unverified_account_ttl: i64,
+    cleanup_interval: u64,
+    wrong_pass_max_count: u32,
+    wrong_pass_ban_time: u32,
+    wrong_pass_ban_type: u8,
+    ip_attempts_max_count: u32,
+    ip_attempts_window: u64,
+    password_min_length: usize,
+    password_require_letter: bool,
+    password_require_digit: bool,
+    password_require_symbol: bool,
+    password_banned_list: Option<String>,
+    ip_location_file: Option<String>,
+    access_expiry_interval: u64,
+    trusted_proxies: Vec<IpAddr>,
+    default_expansion: u8,
+    auth_db: String,
+    db_max_connections: u32,
+    db_min_connections: u32,
+    db_acquire_timeout: u64,
+    db_idle_timeout: u64,
 }
 
 impl Config {
     const DEFAULT_HOST: &'static str = "127.0.0.1";
     const DEFAUTL_PORT: u16 = 8000;
+    const DEFAULT_SESSION_TTL: i64 = 3600;
+    const DEFAULT_TOTP_ISSUER: &'static str = "AzerothCore";
+    const DEFAULT_MAIL_TRANSPORT: &'static str = "log";
+    const DEFAULT_MAIL_FILE: &'static str = "mail.log";
+    const DEFAULT_MAIL_FROM: &'static str = "noreply@localhost";
+    const DEFAULT_PASSWORD_RESET_TTL: i64 = 3600;
+    const DEFAULT_EMAIL_VERIFICATION_TTL: i64 = 86400;
+    const DEFAULT_UNVERIFIED_ACCOUNT_TTL: i64 = 604800;
+    const DEFAULT_CLEANUP_INTERVAL: u64 = 3600;
+    const DEFAULT_WRONG_PASS_BAN_TIME: u32 = 600;
+    const DEFAULT_IP_ATTEMPTS_MAX_COUNT: u32 = 30;
+    const DEFAULT_IP_ATTEMPTS_WINDOW: u64 = 600;
+    const DEFAULT_PASSWORD_MIN_LENGTH: usize = 6;
+    const DEFAULT_ACCESS_EXPIRY_INTERVAL: u64 = 60;
+    const DEFAULT_DB_MAX_CONNECTIONS: u32 = 10;
+    const DEFAULT_DB_ACQUIRE_TIMEOUT: u64 = 30;
+    const DEFAULT_DB_IDLE_TIMEOUT: u64 = 600;
     pub fn from_env() -> Self {
         let host =
             std::env::var("GQL_SERVER_HOST").unwrap_or_else(|_| Self::DEFAULT_HOST.to_string());
@@ -17,19 +76,343 @@ impl Config {
         );
         let is_secure = std::env::var("GQL_SERVER_IS_SECURE")
             .map_or_else(|_| false, |v| v.parse::<bool>().unwrap_or(false));
+        let session_secret = std::env::var("SESSION_SECRET").unwrap_or_else(|_| {
+            warn!("SESSION_SECRET is not set, sessions will not survive a restart");
+            hex::encode(rand::random::<[u8; 32]>())
+        });
+        let session_ttl = std::env::var("SESSION_TTL").map_or_else(
+            |_| Self::DEFAULT_SESSION_TTL,
+            |v| v.parse::<i64>().unwrap_or(Self::DEFAULT_SESSION_TTL),
+        );
+        let expose_account_secrets = std::env::var("EXPOSE_ACCOUNT_SECRETS")
+            .map_or_else(|_| false, |v| v.parse::<bool>().unwrap_or(false));
+        let totp_master_key = std::env::var("TOTP_MASTER_SECRET")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map(|v| {
+                Self::parse_totp_master_key(&v)
+                    .expect("TOTP_MASTER_SECRET must be a hex string of at most 32 characters")
+            });
+        let totp_issuer =
+            std::env::var("TOTP_ISSUER").unwrap_or_else(|_| Self::DEFAULT_TOTP_ISSUER.to_string());
+        let mail_transport = std::env::var("MAIL_TRANSPORT")
+            .unwrap_or_else(|_| Self::DEFAULT_MAIL_TRANSPORT.to_string());
+        let mail_smtp_url = std::env::var("MAIL_SMTP_URL").unwrap_or_default();
+        let mail_file =
+            std::env::var("MAIL_FILE").unwrap_or_else(|_| Self::DEFAULT_MAIL_FILE.to_string());
+        let mail_from =
+            std::env::var("MAIL_FROM").unwrap_or_else(|_| Self::DEFAULT_MAIL_FROM.to_string());
+        let password_reset_url = std::env::var("PASSWORD_RESET_URL")
+            .ok()
+            .filter(|v| !v.is_empty());
+        let password_reset_ttl = std::env::var("PASSWORD_RESET_TTL").map_or_else(
+            |_| Self::DEFAULT_PASSWORD_RESET_TTL,
+            |v| v.parse::<i64>().unwrap_or(Self::DEFAULT_PASSWORD_RESET_TTL),
+        );
+        let require_email_verification = std::env::var("REQUIRE_EMAIL_VERIFICATION")
+            .map_or_else(|_| false, |v| v.parse::<bool>().unwrap_or(false));
+        let email_verification_url = std::env::var("EMAIL_VERIFICATION_URL")
+            .ok()
+            .filter(|v| !v.is_empty());
+        let email_verification_ttl = std::env::var("EMAIL_VERIFICATION_TTL").map_or_else(
+            |_| Self::DEFAULT_EMAIL_VERIFICATION_TTL,
+            |v| {
+                v.parse::<i64>()
+                    .unwrap_or(Self::DEFAULT_EMAIL_VERIFICATION_TTL)
+            },
+        );
+        let unverified_account_ttl = std::env::var("UNVERIFIED_ACCOUNT_TTL").map_or_else(
+            |_| Self::DEFAULT_UNVERIFIED_ACCOUNT_TTL,
+            |v| {
+                v.parse::<i64>()
+                    .unwrap_or(Self::DEFAULT_UNVERIFIED_ACCOUNT_TTL)
+            },
+        );
+        // a zero interval would make the timer of the cleanup task panic
+        let cleanup_interval = std::env::var("CLEANUP_INTERVAL").map_or_else(
+            |_| Self::DEFAULT_CLEANUP_INTERVAL,
+            |v| {
+                v.parse::<u64>()
+                    .ok()
+                    .filter(|v| *v > 0)
+                    .unwrap_or(Self::DEFAULT_CLEANUP_INTERVAL)
+            },
+        );
+        let wrong_pass_max_count = std::env::var("WRONG_PASS_MAX_COUNT")
+            .map_or_else(|_| 0, |v| v.parse::<u32>().unwrap_or(0));
+        let wrong_pass_ban_time = std::env::var("WRONG_PASS_BAN_TIME").map_or_else(
+            |_| Self::DEFAULT_WRONG_PASS_BAN_TIME,
+            |v| {
+                v.parse::<u32>()
+                    .unwrap_or(Self::DEFAULT_WRONG_PASS_BAN_TIME)
+            },
+        );
+        let wrong_pass_ban_type = std::env::var("WRONG_PASS_BAN_TYPE")
+            .map_or_else(|_| 0, |v| v.parse::<u8>().unwrap_or(0));
+        let ip_attempts_max_count = std::env::var("IP_ATTEMPTS_MAX_COUNT").map_or_else(
+            |_| Self::DEFAULT_IP_ATTEMPTS_MAX_COUNT,
+            |v| {
+                v.parse::<u32>()
+                    .unwrap_or(Self::DEFAULT_IP_ATTEMPTS_MAX_COUNT)
+            },
+        );
+        let ip_attempts_window = std::env::var("IP_ATTEMPTS_WINDOW").map_or_else(
+            |_| Self::DEFAULT_IP_ATTEMPTS_WINDOW,
+            |v| v.parse::<u64>().unwrap_or(Self::DEFAULT_IP_ATTEMPTS_WINDOW),
+        );
+        let password_min_length = std::env::var("PASSWORD_MIN_LENGTH").map_or_else(
+            |_| Self::DEFAULT_PASSWORD_MIN_LENGTH,
+            |v| {
+                v.parse::<usize>()
+                    .unwrap_or(Self::DEFAULT_PASSWORD_MIN_LENGTH)
+            },
+        );
+        let password_require_letter = std::env::var("PASSWORD_REQUIRE_LETTER")
+            .map_or_else(|_| false, |v| v.parse::<bool>().unwrap_or(false));
+        let password_require_digit = std::env::var("PASSWORD_REQUIRE_DIGIT")
+            .map_or_else(|_| false, |v| v.parse::<bool>().unwrap_or(false));
+        let password_require_symbol = std::env::var("PASSWORD_REQUIRE_SYMBOL")
+            .map_or_else(|_| false, |v| v.parse::<bool>().unwrap_or(false));
+        let password_banned_list = std::env::var("PASSWORD_BANNED_LIST")
+            .ok()
+            .filter(|v| !v.is_empty());
+        let ip_location_file = std::env::var("IP_LOCATION_FILE")
+            .ok()
+            .filter(|v| !v.is_empty());
+        // like `CLEANUP_INTERVAL`, zero is not a valid timer interval
+        let access_expiry_interval = std::env::var("ACCESS_EXPIRY_INTERVAL").map_or_else(
+            |_| Self::DEFAULT_ACCESS_EXPIRY_INTERVAL,
+            |v| {
+                v.parse::<u64>()
+                    .ok()
+                    .filter(|v| *v > 0)
+                    .unwrap_or(Self::DEFAULT_ACCESS_EXPIRY_INTERVAL)
+            },
+        );
+        let trusted_proxies = std::env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .filter_map(|v| match v.parse::<IpAddr>() {
+                Ok(ip) => Some(ip),
+                Err(_) => {
+                    warn!("TRUSTED_PROXIES entry {} is not an ip address", v);
+                    None
+                }
+            })
+            .collect();
+        let default_expansion = std::env::var("DEFAULT_EXPANSION").map_or_else(
+            |_| Expansion::Wotlk as u8,
+            |v| v.parse::<u8>().unwrap_or(Expansion::Wotlk as u8),
+        );
+        let auth_db = std::env::var("AUTH_DB").expect("AUTH_DB must be set");
+        let db_max_connections = std::env::var("DB_MAX_CONNECTIONS").map_or_else(
+            |_| Self::DEFAULT_DB_MAX_CONNECTIONS,
+            |v| v.parse::<u32>().unwrap_or(Self::DEFAULT_DB_MAX_CONNECTIONS),
+        );
+        let db_min_connections = std::env::var("DB_MIN_CONNECTIONS")
+            .map_or_else(|_| 0, |v| v.parse::<u32>().unwrap_or(0));
+        let db_acquire_timeout = std::env::var("DB_ACQUIRE_TIMEOUT").map_or_else(
+            |_| Self::DEFAULT_DB_ACQUIRE_TIMEOUT,
+            |v| v.parse::<u64>().unwrap_or(Self::DEFAULT_DB_ACQUIRE_TIMEOUT),
+        );
+        let db_idle_timeout = std::env::var("DB_IDLE_TIMEOUT").map_or_else(
+            |_| Self::DEFAULT_DB_IDLE_TIMEOUT,
+            |v| v.parse::<u64>().unwrap_or(Self::DEFAULT_DB_IDLE_TIMEOUT),
+        );
         Self {
             host,
             port,
             is_secure,
+            session_secret,
+            session_ttl,
+            expose_account_secrets,
+            totp_master_key,
+            totp_issuer,
+            mail_transport,
+            mail_smtp_url,
+            mail_file,
+            mail_from,
+            password_reset_url,
+            password_reset_ttl,
+            require_email_verification,
+            email_verification_url,
+            email_verification_ttl,
+            unverified_account_ttl,
+            cleanup_interval,
+            wrong_pass_max_count,
+            wrong_pass_ban_time,
+            wrong_pass_ban_type,
+            ip_attempts_max_count,
+            ip_attempts_window,
+            password_min_length,
+            password_require_letter,
+            password_require_digit,
+            password_require_symbol,
+            password_banned_list,
+            ip_location_file,
+            access_expiry_interval,
+            trusted_proxies,
+            default_expansion,
+            auth_db,
+            db_max_connections,
+            db_min_connections,
+            db_acquire_timeout,
+            db_idle_timeout,
         }
     }
+    /// `TOTPMasterSecret` of authserver.conf is read as a big number,
+    /// the cipher key is its 16 byte little endian representation.
+    fn parse_totp_master_key(hex_str: &str) -> Option<[u8; 16]> {
+        if hex_str.len() > 32 {
+            return None;
+        }
+        let mut key: [u8; 16] = hex::decode(format!("{:0>32}", hex_str))
+            .ok()?
+            .try_into()
+            .ok()?;
+        key.reverse();
+        Some(key)
+    }
     pub fn host(&self) -> String {
         self.host.clone()
     }
     pub fn port(&self) -> u16 {
-        self.port.clone()
+        self.port
     }
     pub fn is_secure(&self) -> bool {
-        self.is_secure.clone()
+        self.is_secure
+    }
+    pub fn session_secret(&self) -> &[u8] {
+        self.session_secret.as_bytes()
+    }
+    /// Session token lifetime in seconds
+    pub fn session_ttl(&self) -> i64 {
+        self.session_ttl
+    }
+    /// Allows administrators to read salt, verifier, session_key and totp_secret of accounts
+    pub fn expose_account_secrets(&self) -> bool {
+        self.expose_account_secrets
+    }
+    /// Key `account.totp_secret` is encrypted with, unencrypted secrets are used when it is not set
+    pub fn totp_master_key(&self) -> Option<&[u8; 16]> {
+        self.totp_master_key.as_ref()
+    }
+    pub fn totp_issuer(&self) -> String {
+        self.totp_issuer.clone()
+    }
+    /// One of `smtp`, `file` or `log`
+    pub fn mail_transport(&self) -> String {
+        self.mail_transport.clone()
+    }
+    pub fn mail_smtp_url(&self) -> String {
+        self.mail_smtp_url.clone()
+    }
+    pub fn mail_file(&self) -> String {
+        self.mail_file.clone()
+    }
+    pub fn mail_from(&self) -> String {
+        self.mail_from.clone()
+    }
+    /// Link sent in password reset mails, `{token}` is replaced with the reset token
+    pub fn password_reset_url(&self) -> Option<String> {
+        self.password_reset_url.clone()
+    }
+    /// Password reset token lifetime in seconds
+    pub fn password_reset_ttl(&self) -> i64 {
+        self.password_reset_ttl
+    }
+    /// Keeps new accounts locked until the token mailed to their `reg_mail` is confirmed
+    pub fn require_email_verification(&self) -> bool {
+        self.require_email_verification
+    }
+    /// Link sent in verification mails, `{token}` is replaced with the verification token
+    pub fn email_verification_url(&self) -> Option<String> {
+        self.email_verification_url.clone()
+    }
+    /// Verification token lifetime in seconds
+    pub fn email_verification_ttl(&self) -> i64 {
+        self.email_verification_ttl
+    }
+    /// Seconds after registration an unverified account is deleted
+    pub fn unverified_account_ttl(&self) -> i64 {
+        self.unverified_account_ttl
+    }
+    /// Seconds between runs of the background cleanup, 0 falls back to the default
+    pub fn cleanup_interval(&self) -> u64 {
+        self.cleanup_interval
+    }
+    /// `WrongPass.MaxCount`, failed logins before a ban, 0 disables bans
+    pub fn wrong_pass_max_count(&self) -> u32 {
+        self.wrong_pass_max_count
+    }
+    /// `WrongPass.BanTime` in seconds, 0 bans permanently
+    pub fn wrong_pass_ban_time(&self) -> u32 {
+        self.wrong_pass_ban_time
+    }
+    /// `WrongPass.BanType`, 0 bans the ip, 1 bans the account
+    pub fn wrong_pass_ban_type(&self) -> BanType {
+        BanType::from(self.wrong_pass_ban_type)
+    }
+    /// Attempts per ip on checks not bound to an account, 0 disables the limit
+    pub fn ip_attempts_max_count(&self) -> u32 {
+        self.ip_attempts_max_count
+    }
+    /// Seconds `IP_ATTEMPTS_MAX_COUNT` applies to
+    pub fn ip_attempts_window(&self) -> u64 {
+        self.ip_attempts_window
+    }
+    /// Shortest password accepted for new passwords, the game client limits them to 16 characters
+    pub fn password_min_length(&self) -> usize {
+        self.password_min_length
+    }
+    pub fn password_require_letter(&self) -> bool {
+        self.password_require_letter
+    }
+    pub fn password_require_digit(&self) -> bool {
+        self.password_require_digit
+    }
+    pub fn password_require_symbol(&self) -> bool {
+        self.password_require_symbol
+    }
+    /// File with one forbidden password per line
+    pub fn password_banned_list(&self) -> Option<String> {
+        self.password_banned_list.clone()
+    }
+    /// `IPLocationFile` of authserver, the IP2Location CSV country locks are checked against
+    pub fn ip_location_file(&self) -> Option<String> {
+        self.ip_location_file.clone()
+    }
+    /// Seconds between checks for expired access grants, 0 falls back to the default
+    pub fn access_expiry_interval(&self) -> u64 {
+        self.access_expiry_interval
+    }
+    /// Proxies whose `X-Forwarded-For` header is believed
+    pub fn trusted_proxies(&self) -> &[IpAddr] {
+        &self.trusted_proxies
+    }
+    /// Expansion of new accounts, 0 classic, 1 tbc and 2 wotlk
+    pub fn default_expansion(&self) -> Expansion {
+        Expansion::from(self.default_expansion)
+    }
+    /// Connection url of the auth database
+    pub fn auth_db(&self) -> String {
+        self.auth_db.clone()
+    }
+    pub fn db_max_connections(&self) -> u32 {
+        self.db_max_connections
+    }
+    /// Connections kept open even when idle
+    pub fn db_min_connections(&self) -> u32 {
+        self.db_min_connections
+    }
+    /// Seconds to wait for a free connection before a query fails
+    pub fn db_acquire_timeout(&self) -> u64 {
+        self.db_acquire_timeout
+    }
+    /// Seconds after which idle connections are closed, 0 keeps them open
+    pub fn db_idle_timeout(&self) -> u64 {
+        self.db_idle_timeout
     }
 }
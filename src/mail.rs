@@ -0,0 +1,103 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use log::{error, info};
+
+use crate::config::Config;
+
+pub type SharedMailer = Arc<dyn Mailer>;
+
+#[derive(Clone, Debug)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: &Mail) -> Result<(), String>;
+}
+
+/// Picks the transport configured with `MAIL_TRANSPORT`
+pub fn get_mailer(config: &Config) -> SharedMailer {
+    match config.mail_transport().as_str() {
+        "smtp" => Arc::new(SmtpMailer::new(&config.mail_smtp_url(), config.mail_from())),
+        "file" => Arc::new(FileMailer::new(config.mail_file())),
+        _ => Arc::new(LogMailer),
+    }
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: String,
+}
+
+impl SmtpMailer {
+    pub fn new(url: &str, from: String) -> Self {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .expect("MAIL_SMTP_URL is not a valid SMTP url")
+            .build();
+        Self { transport, from }
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), String> {
+        let message = Message::builder()
+            .from(self.from.parse().map_err(|_| "Invalid sender address")?)
+            .to(mail.to.parse().map_err(|_| "Invalid recipient address")?)
+            .subject(mail.subject.as_str())
+            .body(mail.body.clone())
+            .map_err(|e| e.to_string())?;
+        match self.transport.send(message).await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err("Mail cannot be sent".to_string())
+            }
+        }
+    }
+}
+
+/// Appends mails to a file instead of sending them, for development and tests
+pub struct FileMailer {
+    path: String,
+}
+
+impl FileMailer {
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), String> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| e.to_string())?;
+        writeln!(
+            file,
+            "To: {}\nSubject: {}\n\n{}\n",
+            mail.to, mail.subject, mail.body
+        )
+        .map_err(|e| e.to_string())
+    }
+}
+
+/// Writes mails to the log
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), String> {
+        info!("Mail to {} \"{}\":\n{}", mail.to, mail.subject, mail.body);
+        Ok(())
+    }
+}
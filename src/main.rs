@@ -1,21 +1,33 @@
-use crate::auth::{account::MutationRoot, db::get_storage};
+use crate::auth::{
+    account::MutationRoot,
+    db::{get_storage, Storage},
+    error::{ApiError, ErrorCodes},
+    guard::{Caller, ClientIp},
+    iplocation::IpLocation,
+    loader::{AccessLoader, RealmCharactersLoader},
+    lockout::AttemptLimiter,
+    validation::Validator,
+};
 use actix_cors::Cors;
 use actix_web::{
     dev::ServiceRequest, guard, http::header::HeaderMap, web, App, Error, HttpRequest,
     HttpResponse, HttpServer, Result,
 };
 use actix_web_httpauth::{
-    extractors::{basic::BasicAuth, bearer::BearerAuth, AuthenticationError},
+    extractors::{basic::BasicAuth, AuthenticationError},
     middleware::HttpAuthentication,
 };
-use async_graphql::{http::GraphiQLSource, Data, Schema};
+use async_graphql::{dataloader::DataLoader, http::GraphiQLSource, Data, Schema};
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
 use auth::account::{AccountSchema, QueryRoot, SubscriptionRoot};
 use config::Config;
-use log::info;
+use log::{debug, info};
+use std::{net::IpAddr, time::Duration};
 
 mod auth;
 pub mod config;
+mod mail;
+mod tasks;
 
 async fn graphiql(config: web::Data<Config>) -> HttpResponse {
     let endpoint = format!("http://{}:{}", config.host(), config.port());
@@ -37,43 +49,137 @@ fn get_token_from_headers(headers: &HeaderMap) -> Option<String> {
         .and_then(|value| value.to_str().map(|s| s.to_string()).ok())
 }
 
+fn get_client_ip(req: &HttpRequest, config: &Config) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    Some(resolve_client_ip(
+        peer,
+        req.headers(),
+        config.trusted_proxies(),
+    ))
+}
+
+/// The peer address, or the last address in `X-Forwarded-For` that was not added by a trusted proxy.
+/// Only entries behind trusted proxies are believed, the walk stops at an entry that is not an address
+/// and the closest known hop is used. When every entry is trusted the first one is the client.
+fn resolve_client_ip(peer: IpAddr, headers: &HeaderMap, trusted: &[IpAddr]) -> IpAddr {
+    if !trusted.contains(&peer) {
+        return peer;
+    }
+    let forwarded = headers
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+    let mut client = peer;
+    for ip in forwarded.iter().rev() {
+        match ip {
+            Some(ip) if trusted.contains(ip) => client = *ip,
+            Some(ip) => return *ip,
+            None => break,
+        }
+    }
+    client
+}
+
 async fn index(
     schema: web::Data<AccountSchema>,
-    _req: HttpRequest,
+    storage: web::Data<Storage>,
+    config: web::Data<Config>,
+    req: HttpRequest,
     gql_request: GraphQLRequest,
 ) -> GraphQLResponse {
-    let request = gql_request.into_inner();
-    // if let Some(token) = get_token_from_headers(req.headers()) {
-    //     request = request.data(token);
-    // }
+    let mut request = gql_request.into_inner();
+    if let Some(ip) = get_client_ip(&req, &config) {
+        request = request.data(ClientIp(ip));
+    }
+    if let Some(token) = get_token_from_headers(req.headers()) {
+        // an invalid token makes the request anonymous, guards report it
+        match Caller::from_token(&storage, &config, &token).await {
+            Ok(caller) => request = request.data(caller),
+            Err(e) => debug!("{:?}", e),
+        }
+    }
     schema.execute(request).await.into()
 }
 
+/// Subscriptions need a session token, either in the `Token` header of the upgrade request
+/// or as `token` in the `connection_init` payload, which takes precedence.
+/// The connection is refused when neither resolves to a valid session.
 async fn index_ws(
     schema: web::Data<AccountSchema>,
+    storage: web::Data<Storage>,
+    config: web::Data<Config>,
     req: HttpRequest,
     payload: web::Payload,
 ) -> Result<HttpResponse> {
-    let mut data = Data::default();
-    if let Some(token) = get_token_from_headers(req.headers()) {
-        data.insert(token);
-    }
+    let header_caller = match get_token_from_headers(req.headers()) {
+        Some(token) => match Caller::from_token(&storage, &config, &token).await {
+            Ok(caller) => Some(caller),
+            Err(e) => {
+                debug!("{:?}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    let client_ip = get_client_ip(&req, &config);
 
     GraphQLSubscription::new(Schema::clone(&*schema))
-        .with_data(data)
-        // .on_connection_init(on_connection_init)
+        .on_connection_init(move |payload| async move {
+            let caller = match get_token_from_payload(&payload) {
+                Some(token) => Caller::from_token(&storage, &config, &token).await?,
+                None => header_caller
+                    .ok_or_else(|| ApiError::Unauthenticated("Not logged in".to_string()))?,
+            };
+            let mut data = Data::default();
+            data.insert(caller);
+            if let Some(ip) = client_ip {
+                data.insert(ClientIp(ip));
+            }
+            Ok(data)
+        })
         .start(&req, payload)
 }
+
+fn get_token_from_payload(payload: &serde_json::Value) -> Option<String> {
+    ["token", "Token"]
+        .iter()
+        .find_map(|key| payload.get(key))
+        .and_then(|value| value.as_str().map(|s| s.to_string()))
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let _ = dotenv::dotenv().ok();
+    env_logger::init();
     // let config = Config::from_env();
     let config = web::Data::new(Config::from_env());
-    env_logger::init();
+    let storage = get_storage(&config).await;
     let schema = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
-        .data(get_storage().await)
+        .extension(ErrorCodes)
+        .data(storage.clone())
+        .data(config.get_ref().clone())
+        .data(mail::get_mailer(&config))
+        .data(AttemptLimiter::new(
+            config.ip_attempts_max_count(),
+            Duration::from_secs(config.ip_attempts_window()),
+        ))
+        .data(Validator::new(&config))
+        .data(IpLocation::new(&config))
+        .data(DataLoader::new(
+            AccessLoader::new(storage.clone()),
+            actix_web::rt::spawn,
+        ))
+        .data(DataLoader::new(
+            RealmCharactersLoader::new(storage.clone()),
+            actix_web::rt::spawn,
+        ))
         .finish();
 
+    tasks::spawn_cleanup(storage.clone(), config.get_ref().clone());
+    tasks::spawn_access_expiry(storage.clone(), config.get_ref().clone());
+
     info!("GraphiQL IDE: http://{}:{}", config.host(), config.port());
     let (server_host, server_port) = { (config.host(), config.port()) };
     HttpServer::new(move || {
@@ -84,6 +190,7 @@ async fn main() -> std::io::Result<()> {
             // add headers to error responses
             .wrap(Cors::permissive())
             .app_data(web::Data::new(schema.clone()))
+            .app_data(web::Data::new(storage.clone()))
             .app_data(config.clone())
             .service(web::resource("/").guard(guard::Get()).to(graphiql))
             .service(web::resource("/").guard(guard::Post()).to(index))
@@ -98,13 +205,81 @@ async fn ok_validator(
     req: ServiceRequest,
     credentials: BasicAuth,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
-    eprintln!("{:?}", credentials);
+    debug!("Basic auth for user {}", credentials.user_id());
     let http_user = std::env::var("HTTP_AUTH_USER").unwrap_or("".to_string());
     let http_pass = std::env::var("HTTP_AUTH_PASSWORD").unwrap_or("".to_string());
-    if credentials.user_id().eq(http_user.as_str()) && credentials.password().eq(&Some(http_pass.as_str())) {
+    if credentials.user_id().eq(http_user.as_str())
+        && credentials.password().eq(&Some(http_pass.as_str()))
+    {
         Ok(req)
     } else {
         use actix_web_httpauth::headers::www_authenticate::basic::Basic;
         Err((Error::from(AuthenticationError::new(Basic::new())), req))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    use super::*;
+
+    const PEER: &str = "10.0.0.1";
+    const PROXY: &str = "10.0.0.2";
+
+    fn resolve(peer: &str, forwarded: &[&str], trusted: &[&str]) -> IpAddr {
+        let mut headers = HeaderMap::new();
+        for value in forwarded {
+            headers.append(
+                HeaderName::from_static("x-forwarded-for"),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        let trusted = trusted
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect::<Vec<_>>();
+        resolve_client_ip(peer.parse().unwrap(), &headers, &trusted)
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn untrusted_peers_cannot_spoof_the_client() {
+        assert_eq!(resolve(PEER, &["1.2.3.4"], &[]), ip(PEER));
+        assert_eq!(resolve(PEER, &["1.2.3.4"], &[PROXY]), ip(PEER));
+    }
+
+    #[test]
+    fn trusted_proxy_chains_are_followed_to_the_client() {
+        assert_eq!(
+            resolve(PEER, &["6.6.6.6, 1.2.3.4, 10.0.0.2"], &[PEER, PROXY]),
+            ip("1.2.3.4")
+        );
+        assert_eq!(
+            resolve(PEER, &["6.6.6.6, 1.2.3.4", "10.0.0.2"], &[PEER, PROXY]),
+            ip("1.2.3.4")
+        );
+    }
+
+    #[test]
+    fn the_first_entry_is_the_client_when_all_are_trusted() {
+        assert_eq!(
+            resolve(PEER, &["10.0.0.2, 10.0.0.1"], &[PEER, PROXY]),
+            ip(PROXY)
+        );
+        assert_eq!(resolve(PEER, &[], &[PEER]), ip(PEER));
+    }
+
+    #[test]
+    fn garbage_entries_stop_at_the_closest_known_hop() {
+        assert_eq!(
+            resolve(PEER, &["1.2.3.4, unknown, 10.0.0.2"], &[PEER, PROXY]),
+            ip(PROXY)
+        );
+        assert_eq!(resolve(PEER, &["1.2.3.4, , "], &[PEER]), ip(PEER));
+        assert_eq!(resolve(PEER, &["garbage, 5.6.7.8"], &[PEER]), ip("5.6.7.8"));
+    }
+}
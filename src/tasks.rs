@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use chrono::Duration as ChronoDuration;
+use log::{error, info};
+use sqlx::types::chrono::Utc;
+
+use crate::auth::db::Storage;
+use crate::config::Config;
+
+/// Periodically removes data that expired, runs every `CLEANUP_INTERVAL` seconds
+pub fn spawn_cleanup(db: Storage, config: Config) {
+    actix_web::rt::spawn(async move {
+        let mut interval =
+            actix_web::rt::time::interval(Duration::from_secs(config.cleanup_interval()));
+        loop {
+            interval.tick().await;
+            if let Err(e) = db.delete_expired_sessions().await {
+                error!("{:?}", e);
+            }
+            let joined_before =
+                Utc::now() - ChronoDuration::seconds(config.unverified_account_ttl());
+            match db.delete_unverified_accounts(joined_before).await {
+                Ok(0) => {}
+                Ok(n) => info!("Deleted {} accounts that never verified their email", n),
+                Err(e) => error!("{:?}", e),
+            }
+        }
+    });
+}
+
+/// Removes `account_access` rows of time-limited grants once they run out,
+/// checks every `ACCESS_EXPIRY_INTERVAL` seconds
+pub fn spawn_access_expiry(db: Storage, config: Config) {
+    actix_web::rt::spawn(async move {
+        let mut interval =
+            actix_web::rt::time::interval(Duration::from_secs(config.access_expiry_interval()));
+        loop {
+            interval.tick().await;
+            match db.expire_access_grants().await {
+                Ok(grants) => {
+                    for (account_id, realm_id) in grants {
+                        info!(
+                            "Access of account {} on realm {} expired and was removed",
+                            account_id, realm_id
+                        );
+                    }
+                }
+                Err(e) => error!("{:?}", e),
+            }
+        }
+    });
+}